@@ -3,7 +3,9 @@ use bevy::{
     log::{Level, LogPlugin, LogSettings},
     prelude::*,
 };
-use bevy_remote_devtools_plugin::{dev_named, RemoteDevToolsPlugin};
+use bevy_remote_devtools_plugin::{
+    dev_named, RemoteDevToolsAppExt, RemoteDevToolsPlugin, TrackedEntityCommandsExt,
+};
 
 fn main() {
     let mut builder = env_logger::Builder::new();
@@ -17,9 +19,10 @@ fn main() {
         })
         .insert_resource(Msaa { samples: 4 })
         .add_plugins_with(DefaultPlugins, |group| group.disable::<LogPlugin>())
-        .add_plugin(RemoteDevToolsPlugin::new("3D Example", 3030))
+        .add_plugin(RemoteDevToolsPlugin::new("3D Example", 3030).with_spawning_systems_tracked())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .add_startup_system(setup)
+        .finish_devtools()
         .run();
 }
 
@@ -45,7 +48,7 @@ fn setup(
             transform: Transform::from_xyz(0.0, 0.5, 0.0),
             ..Default::default()
         })
-        .insert(dev_named!("Cube"));
+        .insert_tracked("Cube");
     // light
     commands
         .spawn_bundle(PointLightBundle {
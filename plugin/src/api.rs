@@ -1,8 +1,8 @@
 use bevy::{
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
-    prelude::DynamicScene,
+    prelude::{DynamicScene, World},
     reflect::TypeRegistryArc,
-    scene::serde::SceneSerializer,
+    scene::serde::ComponentsSerializer,
     window::Windows,
 };
 use rweb::*;
@@ -12,13 +12,25 @@ use std::{convert::Infallible, sync::Mutex};
 use crate::{
     assets::{assets, get_asset_mesh},
     render_graph::get_render_graph,
+    resources::{get_resource, patch_resource, resources},
+    schedule::{schedule, schedule_ambiguities},
     serialization::NumberToStringSerializer,
     sync::{execute_in_world, ExecutionChannel},
-    tracing_tracking::{get_tracing_events, trace_frames},
-    DevToolsSettings,
+    system_toggles::{set_system_toggle, system_toggles},
+    time_control::{pause, resume, set_time_scale, step, time_control},
+    tracing_tracking::{
+        captures, delete_capture, download_capture, get_profiler_summary, get_tracing_events,
+        trace_frames,
+    },
+    types::types,
+    world::{
+        clone_entity, despawn_entity, entity_label, hierarchy, insert_component, inspect_entity,
+        patch_component, query_world, remove_component, set_parent, spawn_entity, world_stream,
+    },
+    DevToolsSettings, ScaledTime,
 };
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Serialize, Schema, Debug)]
 struct Info {
@@ -26,25 +38,35 @@ struct Info {
     version: String,
 }
 
+/// Name of the app, the window title if it wasn't given to the plugin.
+pub(crate) fn app_name(world: &World) -> String {
+    let name = world
+        .get_resource::<DevToolsSettings>()
+        .map(|settings| settings.name.clone())
+        .flatten();
+    let window_title = world
+        .get_resource::<Windows>()
+        .map(|windows| {
+            windows
+                .get_primary()
+                .map(|primary| primary.title().to_string())
+        })
+        .flatten();
+    name.or(window_title).unwrap_or_else(|| "Bevy".to_string())
+}
+
+/// Version of the app, the version of the plugin if it wasn't given to the plugin.
+pub(crate) fn app_version(world: &World) -> String {
+    world
+        .get_resource::<DevToolsSettings>()
+        .and_then(|settings| settings.app_version.clone())
+        .unwrap_or_else(|| VERSION.to_string())
+}
+
 #[get("/v1/info")]
 #[cors(origins("*"))]
 async fn info() -> Result<Json<Info>, Infallible> {
-    let name = execute_in_world(ExecutionChannel::FrameStart, |world| {
-        let name = world
-            .get_resource::<DevToolsSettings>()
-            .map(|settings| settings.name.clone())
-            .flatten();
-        let window_title = world
-            .get_resource::<Windows>()
-            .map(|windows| {
-                windows
-                    .get_primary()
-                    .map(|primary| primary.title().to_string())
-            })
-            .flatten();
-        name.or(window_title).unwrap_or_else(|| "Bevy".to_string())
-    })
-    .await;
+    let name = execute_in_world(ExecutionChannel::FrameStart, |world| app_name(world)).await;
 
     Ok(Info {
         name,
@@ -58,28 +80,39 @@ async fn info() -> Result<Json<Info>, Infallible> {
 struct FrameDiagnostics {
     fps: Option<f64>,
     frame_time: Option<f64>,
+    time_scale: f32,
 }
 
 #[get("/v1/diagnostics/frame")]
 #[cors(origins("*"))]
 async fn diagnostics_frame() -> Result<Json<FrameDiagnostics>, Infallible> {
     let output = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        let mut output = FrameDiagnostics {
+            time_scale: world.get_resource::<ScaledTime>().unwrap().scale(),
+            ..Default::default()
+        };
         if let Some(diagnostics) = world.get_resource::<Diagnostics>() {
-            let fps = diagnostics
+            output.fps = diagnostics
                 .get(FrameTimeDiagnosticsPlugin::FPS)
                 .map(|fps| fps.value())
                 .flatten();
-            let frame_time = diagnostics
+            output.frame_time = diagnostics
                 .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
                 .map(|time| time.value())
                 .flatten();
-            Some(FrameDiagnostics { fps, frame_time })
-        } else {
-            None
         }
+        output
     })
     .await;
-    Ok(output.unwrap_or_default().into())
+    Ok(output.into())
+}
+
+/// Entity of the scene format extended with the label the tools show for it.
+#[derive(Serialize)]
+struct LabeledEntity<'a> {
+    entity: u32,
+    label: String,
+    components: ComponentsSerializer<'a>,
 }
 
 #[get("/v1/world")]
@@ -88,8 +121,23 @@ async fn world() -> Result<String, Infallible> {
     let json = execute_in_world(ExecutionChannel::FrameEnd, |world| {
         let type_registry = world.get_resource::<TypeRegistryArc>().unwrap();
         let scene = DynamicScene::from_world(world, type_registry);
-        let serializer = SceneSerializer::new(&scene, type_registry);
-        serde_json::to_string(&NumberToStringSerializer(serializer)).unwrap()
+        let entities = scene
+            .entities
+            .iter()
+            .map(|entity| LabeledEntity {
+                entity: entity.entity,
+                label: world
+                    .entities()
+                    .resolve_from_id(entity.entity)
+                    .map(|resolved| entity_label(world, resolved))
+                    .unwrap_or_default(),
+                components: ComponentsSerializer {
+                    components: &entity.components,
+                    registry: type_registry,
+                },
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string(&NumberToStringSerializer(entities)).unwrap()
     })
     .await;
 
@@ -103,9 +151,34 @@ async fn api_main(port: u16) {
             .or(get_render_graph().boxed())
             .or(info().boxed())
             .or(world().boxed())
+            .or(query_world().boxed())
+            .or(hierarchy().boxed())
+            .or(inspect_entity().boxed())
+            .or(patch_component().boxed())
+            .or(insert_component().boxed())
+            .or(remove_component().boxed())
+            .or(spawn_entity().boxed())
+            .or(despawn_entity().boxed())
+            .or(clone_entity().boxed())
+            .or(set_parent().boxed())
+            .or(resources().boxed())
+            .or(get_resource().boxed())
+            .or(patch_resource().boxed())
+            .or(types().boxed())
+            .or(schedule().boxed())
+            .or(schedule_ambiguities().boxed())
+            .or(system_toggles().boxed())
+            .or(set_system_toggle().boxed())
+            .or(time_control().boxed())
+            .or(pause().boxed())
+            .or(resume().boxed())
+            .or(step().boxed())
+            .or(set_time_scale().boxed())
             .or(assets().boxed())
             .or(get_asset_mesh().boxed())
-            .or(trace_frames().boxed())
+            .or(get_profiler_summary().boxed())
+            .or(captures().boxed())
+            .or(delete_capture().boxed())
             .or(diagnostics_frame().boxed())
             .boxed()
     });
@@ -121,12 +194,19 @@ async fn api_main(port: u16) {
             "Access-Control-Request-Headers",
             "Content-Type",
         ])
-        .allow_methods(vec!["POST", "GET"])
+        .allow_methods(vec!["POST", "GET", "PATCH", "DELETE"])
         .build();
 
-    serve(filter.or(openapi_docs(spec)).with(cors))
-        .run(([0, 0, 0, 0], port))
-        .await;
+    serve(
+        filter
+            .or(openapi_docs(spec))
+            .or(world_stream())
+            .or(trace_frames())
+            .or(download_capture())
+            .with(cors),
+    )
+    .run(([0, 0, 0, 0], port))
+    .await;
 }
 
 pub(crate) fn start(port: u16) {
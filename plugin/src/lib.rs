@@ -2,53 +2,176 @@ use bevy::{
     prelude::*,
     render::{RenderApp, RenderStage},
 };
+use serde::Serialize;
+use std::path::PathBuf;
 use sync::*;
 
+pub use reflect::{ReflectDefault, ReflectResource};
+pub use schedule::RemoteDevToolsAppExt;
+pub use spawn_tracking::{TrackedCommandsExt, TrackedEntityCommandsExt};
+pub use system_toggles::{toggleable, SystemToggles};
+pub use time_control::ScaledTime;
+pub use tracing_tracking::{DevTools, TraceItem, TraceNameFn, TraceTriggers, TracingSettings};
+
+/// Implements `openapi::Entity` for request bodies that carry a `serde_json::Value`, which
+/// has no schema. They are described as free-form JSON object.
+macro_rules! free_form_schema {
+    ($ty:ident) => {
+        impl rweb::openapi::Entity for $ty {
+            fn type_name() -> rweb::rt::Cow<'static, str> {
+                stringify!($ty).into()
+            }
+            fn describe(
+                comp_d: &mut rweb::openapi::ComponentDescriptor,
+            ) -> rweb::openapi::ComponentOrInlineSchema {
+                comp_d.describe_component(stringify!($ty), |_| rweb::openapi::Schema {
+                    schema_type: Some(rweb::openapi::Type::Object),
+                    ..Default::default()
+                })
+            }
+        }
+    };
+}
+
 mod api;
 mod assets;
+mod reflect;
 mod render_graph;
+mod resources;
+mod schedule;
 mod serialization;
+mod spawn_tracking;
 mod sync;
+mod system_toggles;
+mod time_control;
 mod tracing_tracking;
+mod types;
+mod world;
 
 pub struct RemoteDevToolsPlugin {
     pub port: u16,
     pub name: Option<String>,
+    /// Version recorded in saved trace captures, the version of the plugin if not set.
+    pub app_version: Option<String>,
+    pub name_precedence: NamePrecedence,
+    /// Records the spawning system in `DevInfo` of entities spawned with `spawn_tracked`.
+    pub track_spawning_systems: bool,
+    /// Directory trace captures are saved to when requested.
+    pub capture_dir: Option<PathBuf>,
+    /// Conditions that save the last frames as trace capture to `capture_dir`.
+    pub trace_triggers: Option<TraceTriggers>,
+    pub tracing: TracingSettings,
 }
 
 impl RemoteDevToolsPlugin {
     pub fn new(name: &str, port: u16) -> Self {
         Self {
             name: Some(name.to_string()),
+            app_version: None,
             port,
+            name_precedence: NamePrecedence::default(),
+            track_spawning_systems: false,
+            capture_dir: None,
+            trace_triggers: None,
+            tracing: TracingSettings::default(),
         }
     }
+
+    pub fn with_app_version(mut self, app_version: impl Into<String>) -> Self {
+        self.app_version = Some(app_version.into());
+        self
+    }
+
+    pub fn with_name_precedence(mut self, name_precedence: NamePrecedence) -> Self {
+        self.name_precedence = name_precedence;
+        self
+    }
+
+    pub fn with_spawning_systems_tracked(mut self) -> Self {
+        self.track_spawning_systems = true;
+        self
+    }
+
+    pub fn with_capture_dir(mut self, capture_dir: impl Into<PathBuf>) -> Self {
+        self.capture_dir = Some(capture_dir.into());
+        self
+    }
+
+    pub fn with_tracing_settings(mut self, tracing: TracingSettings) -> Self {
+        self.tracing = tracing;
+        self
+    }
+
+    pub fn with_trace_triggers(mut self, trace_triggers: TraceTriggers) -> Self {
+        self.trace_triggers = Some(trace_triggers);
+        self
+    }
 }
 
 impl Default for RemoteDevToolsPlugin {
     fn default() -> Self {
         Self {
             name: None,
+            app_version: None,
             port: 3030,
+            name_precedence: NamePrecedence::default(),
+            track_spawning_systems: false,
+            capture_dir: None,
+            trace_triggers: None,
+            tracing: TracingSettings::default(),
         }
     }
 }
 
+/// Which label is shown for entities that have both a `Name` and a `DevInfo`. Entities with
+/// neither are shown with their id.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NamePrecedence {
+    #[default]
+    Name,
+    DevInfo,
+}
+
 pub(crate) struct DevToolsSettings {
     name: Option<String>,
+    app_version: Option<String>,
+    name_precedence: NamePrecedence,
+    capture_dir: Option<PathBuf>,
 }
 
 impl Plugin for RemoteDevToolsPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(DevToolsSettings {
             name: self.name.clone(),
+            app_version: self.app_version.clone(),
+            name_precedence: self.name_precedence,
+            capture_dir: self.capture_dir.clone(),
         });
 
         app.register_type::<DevInfo>();
+        app.add_startup_system(reflect::register_builtin_type_data.exclusive_system());
 
-        tracing_tracking::init(app);
+        tracing_tracking::RECORD_SYSTEM_NAMES.store(
+            self.track_spawning_systems,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        tracing_tracking::init(app, self.tracing.clone());
         api::start(self.port);
 
+        // Created after tracing is set up so a failure can be logged.
+        if let Some(capture_dir) = &self.capture_dir {
+            if std::fs::create_dir_all(capture_dir).is_err() {
+                warn!(
+                    "Could not create capture directory {}",
+                    capture_dir.display()
+                );
+            }
+        }
+
+        app.init_resource::<time_control::TimeControl>();
+        app.init_resource::<ScaledTime>();
+        app.init_resource::<SystemToggles>();
+
         app.add_stage_before(
             CoreStage::First,
             "devtools_begin",
@@ -63,20 +186,45 @@ impl Plugin for RemoteDevToolsPlugin {
             "devtools_begin",
             execute_world_tasks_begin.exclusive_system(),
         );
+        app.add_system_to_stage("devtools_begin", time_control::update_time_control);
         app.add_system_to_stage("devtools_end", execute_world_tasks_end.exclusive_system());
+        app.add_system_to_stage("devtools_end", tracing_tracking::merge_profiler_samples);
+        app.init_resource::<world::WorldStreamState>();
+        app.add_system_to_stage(
+            "devtools_end",
+            world::stream_world_changes.exclusive_system(),
+        );
+        match (&self.trace_triggers, &self.capture_dir) {
+            (Some(triggers), Some(capture_dir)) => tracing_tracking::enable_trace_triggers(
+                app,
+                triggers.clone(),
+                capture_dir.clone(),
+                api::app_name(&app.world),
+                api::app_version(&app.world),
+            ),
+            (Some(_), None) => warn!("Trace triggers are ignored without a capture directory"),
+            _ => {}
+        }
 
         app.sub_app_mut(RenderApp).add_system_to_stage(
             RenderStage::Render,
             execute_world_tasks_render_app.exclusive_system(),
         );
+
+        app.capture_devtools_schedule();
     }
 }
 
-#[derive(Component, Reflect, Default)]
-#[reflect(Component)]
+#[derive(Component, Reflect, Default, Serialize)]
+#[reflect(Component, Default)]
 pub struct DevInfo {
     pub name: String,
     pub module: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    /// System that spawned the entity, empty if unknown.
+    pub system: String,
 }
 
 #[macro_export]
@@ -85,6 +233,10 @@ macro_rules! dev_named {
         $crate::DevInfo {
             name: $name.to_string(),
             module: module_path!().to_string(),
+            file: file!().to_string(),
+            line: line!(),
+            column: column!(),
+            ..Default::default()
         }
     };
 }
@@ -0,0 +1,159 @@
+use bevy::{
+    ecs::system::Resource,
+    prelude::*,
+    reflect::{FromType, ReflectRef, TypeRegistryArc},
+};
+
+/// Type data that allows the devtools to construct a default instance of a reflected type.
+/// Register it with `#[reflect(Default)]` on any type that implements `Default`.
+#[derive(Clone)]
+pub struct ReflectDefault {
+    default: fn() -> Box<dyn Reflect>,
+}
+
+impl ReflectDefault {
+    pub fn default(&self) -> Box<dyn Reflect> {
+        (self.default)()
+    }
+}
+
+impl<T: Reflect + Default> FromType<T> for ReflectDefault {
+    fn from_type() -> Self {
+        ReflectDefault {
+            default: || Box::new(T::default()),
+        }
+    }
+}
+
+/// Type data that allows the devtools to read and write a resource through reflection.
+/// Register it with `#[reflect(Resource)]` next to `register_type` of the resource.
+#[derive(Clone)]
+pub struct ReflectResource {
+    insert: fn(&mut World, &dyn Reflect) -> bool,
+    reflect: fn(&World) -> Option<&dyn Reflect>,
+    reflect_mut: fn(&mut World) -> Option<&mut dyn Reflect>,
+}
+
+impl ReflectResource {
+    /// Inserts the resource, constructed from world and patched with `resource`. Returns
+    /// `false` without inserting anything if `resource` doesn't fit the resource type.
+    pub fn insert(&self, world: &mut World, resource: &dyn Reflect) -> bool {
+        (self.insert)(world, resource)
+    }
+
+    pub fn reflect<'a>(&self, world: &'a World) -> Option<&'a dyn Reflect> {
+        (self.reflect)(world)
+    }
+
+    /// Mutable access to the resource, marks it as changed.
+    pub fn reflect_mut<'a>(&self, world: &'a mut World) -> Option<&'a mut dyn Reflect> {
+        (self.reflect_mut)(world)
+    }
+}
+
+impl<T: Resource + Reflect + FromWorld> FromType<T> for ReflectResource {
+    fn from_type() -> Self {
+        ReflectResource {
+            insert: |world, reflected_resource| {
+                let mut resource = T::from_world(world);
+                if !can_apply(&resource, reflected_resource) {
+                    return false;
+                }
+                resource.apply(reflected_resource);
+                world.insert_resource(resource);
+                true
+            },
+            reflect: |world| world.get_resource::<T>().map(|r| r as &dyn Reflect),
+            reflect_mut: |world| {
+                world
+                    .get_resource_mut::<T>()
+                    .map(|r| r.into_inner() as &mut dyn Reflect)
+            },
+        }
+    }
+}
+
+/// Whether `value` can be applied onto `target` without one of the panics of `Reflect::apply`,
+/// which bevy raises when a value or one of its fields has another type than the target.
+pub(crate) fn can_apply(target: &dyn Reflect, value: &dyn Reflect) -> bool {
+    can_apply_fields(target, value, false)
+}
+
+/// With `complete` the value is pushed onto a list instead, which converts it with
+/// `FromReflect` and needs every field.
+fn can_apply_fields(target: &dyn Reflect, value: &dyn Reflect, complete: bool) -> bool {
+    if target.any().type_id() == value.any().type_id() {
+        return true;
+    }
+    let field_fits = |target: Option<&dyn Reflect>, value: &dyn Reflect| match target {
+        Some(target) => can_apply_fields(target, value, complete),
+        None => true,
+    };
+    match (target.reflect_ref(), value.reflect_ref()) {
+        (ReflectRef::Struct(target), ReflectRef::Struct(value)) => {
+            value
+                .iter_fields()
+                .enumerate()
+                .all(|(i, field)| field_fits(target.field(value.name_at(i).unwrap()), field))
+                && (!complete
+                    || (0..target.field_len())
+                        .all(|i| value.field(target.name_at(i).unwrap()).is_some()))
+        }
+        (ReflectRef::TupleStruct(target), ReflectRef::TupleStruct(value)) => {
+            value
+                .iter_fields()
+                .enumerate()
+                .all(|(i, field)| field_fits(target.field(i), field))
+                && (!complete || value.field_len() >= target.field_len())
+        }
+        (ReflectRef::Tuple(target), ReflectRef::Tuple(value)) => {
+            value
+                .iter_fields()
+                .enumerate()
+                .all(|(i, field)| field_fits(target.field(i), field))
+                && (!complete || value.field_len() >= target.field_len())
+        }
+        // Items past the end of the list are pushed, they are checked against the first item
+        // as there is no other way to learn the item type.
+        (ReflectRef::List(target), ReflectRef::List(value)) => {
+            value
+                .iter()
+                .enumerate()
+                .all(|(i, item)| match target.get(i) {
+                    Some(target) => can_apply_fields(target, item, complete),
+                    None => match target.get(0) {
+                        Some(target) => can_apply_fields(target, item, true),
+                        None => false,
+                    },
+                })
+        }
+        // Only existing entries are applied, new ones would need to be converted.
+        (ReflectRef::Map(target), ReflectRef::Map(value)) => {
+            (!complete || value.is_empty())
+                && value
+                    .iter()
+                    .all(|(key, item)| field_fits(target.get(key), item))
+        }
+        _ => false,
+    }
+}
+
+/// Adds `ReflectDefault` to bevys own commonly used components. Runs as startup system so
+/// all plugins had the chance to register their types.
+pub(crate) fn register_builtin_type_data(world: &mut World) {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let mut type_registry = type_registry.write();
+
+    insert_type_data::<Transform, ReflectDefault>(&mut type_registry);
+    insert_type_data::<GlobalTransform, ReflectDefault>(&mut type_registry);
+    insert_type_data::<Name, ReflectDefault>(&mut type_registry);
+    insert_type_data::<Visibility, ReflectDefault>(&mut type_registry);
+}
+
+fn insert_type_data<T: Reflect, D: FromType<T> + Clone + Send + Sync + 'static>(
+    type_registry: &mut bevy::reflect::TypeRegistryInternal,
+) {
+    if let Some(registration) = type_registry.get_mut(std::any::TypeId::of::<T>()) {
+        registration.insert(D::from_type());
+    }
+}
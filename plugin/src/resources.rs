@@ -0,0 +1,212 @@
+use bevy::{
+    prelude::*,
+    reflect::{serde::ReflectSerializer, GetPath, TypeRegistryArc},
+};
+use rweb::{reject::custom, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    reflect::ReflectResource,
+    serialization::NumberToStringSerializer,
+    sync::{execute_in_world, ExecutionChannel},
+    world::{apply_value, deserialize_value, WorldErrors},
+};
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct ResourceOverview {
+    name: String,
+    /// Whether the resource is registered with `ReflectResource` and can be read and written.
+    reflectable: bool,
+}
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct ResourceName {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ResourcePatch {
+    resource: String,
+    #[serde(default)]
+    path: String,
+    value: serde_json::Value,
+}
+
+free_form_schema!(ResourcePatch);
+
+/// Lists every resource of the world. Bevys own resources aren't reflected yet and are only
+/// listed by name.
+#[get("/v1/resources")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn resources() -> Result<Json<Vec<ResourceOverview>>, rweb::Rejection> {
+    let resources = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+        let type_registry = type_registry.read();
+
+        let mut resources = world
+            .archetypes()
+            .resource()
+            .unique_components()
+            .indices()
+            .filter_map(|component_id| world.components().get_info(component_id))
+            .map(|info| ResourceOverview {
+                name: info.name().to_string(),
+                reflectable: info
+                    .type_id()
+                    .and_then(|type_id| type_registry.get(type_id))
+                    .and_then(|registration| registration.data::<ReflectResource>())
+                    .is_some(),
+            })
+            .collect::<Vec<_>>();
+        resources.sort_by(|a, b| a.name.cmp(&b.name));
+        resources
+    })
+    .await;
+
+    Ok(resources.into())
+}
+
+#[get("/v1/resource")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn get_resource(#[query] query: ResourceName) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        serialize_resource(world, &query.name)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn serialize_resource(world: &World, name: &str) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let resource = type_registry
+        .get_with_name(name)
+        .ok_or(WorldErrors::UnknownType)?
+        .data::<ReflectResource>()
+        .ok_or(WorldErrors::NotAResource)?
+        .reflect(world)
+        .ok_or(WorldErrors::ResourceNotFound)?;
+    let serializer = ReflectSerializer::new(resource, &type_registry);
+    Ok(serde_json::to_string(&NumberToStringSerializer(serializer)).unwrap())
+}
+
+/// Writes `value` into the field at `path` of a resource, an empty path replaces the whole
+/// resource. A resource that doesn't exist yet is inserted.
+#[patch("/v1/resource")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn patch_resource(
+    #[json] patch: ResourcePatch,
+) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        apply_resource_patch(world, patch)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn apply_resource_patch(world: &mut World, patch: ResourcePatch) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let registration = type_registry
+        .get_with_name(&patch.resource)
+        .ok_or(WorldErrors::UnknownType)?;
+    let reflect_resource = registration
+        .data::<ReflectResource>()
+        .ok_or(WorldErrors::NotAResource)?;
+
+    if reflect_resource.reflect(world).is_none() {
+        if !patch.path.is_empty() {
+            return Err(WorldErrors::ResourceNotFound);
+        }
+        let value = deserialize_value(&type_registry, registration.name(), patch.value)?;
+        if value.type_name() != registration.name() || !reflect_resource.insert(world, &*value) {
+            return Err(WorldErrors::InvalidValue);
+        }
+    } else {
+        let resource = reflect_resource.reflect_mut(world).unwrap();
+        let field = resource
+            .path_mut(&patch.path)
+            .map_err(|_| WorldErrors::InvalidPath)?;
+        let value = deserialize_value(&type_registry, field.type_name(), patch.value)?;
+        apply_value(field, value)?;
+    }
+
+    drop(type_registry);
+    serialize_resource(world, &patch.resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Reflect, Default)]
+    struct Window {
+        width: f32,
+    }
+
+    #[derive(Reflect, Default)]
+    #[reflect(Resource)]
+    struct Settings {
+        window: Window,
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        let type_registry = TypeRegistryArc::default();
+        {
+            let mut type_registry = type_registry.write();
+            type_registry.register::<f32>();
+            type_registry.register::<String>();
+            type_registry.register::<Settings>();
+        }
+        world.insert_resource(type_registry);
+        world
+    }
+
+    fn patch(path: &str, width: serde_json::Value) -> ResourcePatch {
+        let name = std::any::type_name::<Settings>();
+        ResourcePatch {
+            resource: name.to_string(),
+            path: path.to_string(),
+            value: json!({
+                "type": name,
+                "struct": {
+                    "window": {
+                        "type": std::any::type_name::<Window>(),
+                        "struct": { "width": width },
+                    },
+                },
+            }),
+        }
+    }
+
+    #[test]
+    fn nested_mismatch_is_rejected() {
+        let mut world = world();
+        let invalid = json!({ "type": "alloc::string::String", "value": "a" });
+        let result = apply_resource_patch(&mut world, patch("", invalid.clone()));
+        assert!(matches!(result, Err(WorldErrors::InvalidValue)));
+        assert!(world.get_resource::<Settings>().is_none());
+
+        world.insert_resource(Settings::default());
+        let result = apply_resource_patch(&mut world, patch("", invalid));
+        assert!(matches!(result, Err(WorldErrors::InvalidValue)));
+        assert_eq!(world.resource::<Settings>().window.width, 0.0);
+    }
+
+    #[test]
+    fn patches_insert_and_update_resources() {
+        let mut world = world();
+        let width = json!({ "type": "f32", "value": 2.0 });
+        apply_resource_patch(&mut world, patch("", width)).unwrap();
+        assert_eq!(world.resource::<Settings>().window.width, 2.0);
+
+        let width = json!({ "type": "f32", "value": 3.0 });
+        apply_resource_patch(&mut world, patch("", width)).unwrap();
+        assert_eq!(world.resource::<Settings>().window.width, 3.0);
+    }
+}
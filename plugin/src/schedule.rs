@@ -0,0 +1,598 @@
+use std::convert::Infallible;
+
+use bevy::{
+    ecs::{
+        component::ComponentId,
+        query::Access,
+        schedule::{StageLabel, SystemContainer},
+    },
+    prelude::*,
+    render::RenderApp,
+};
+use rweb::*;
+use serde::Serialize;
+
+use crate::{
+    sync::{execute_in_world, ExecutionChannel},
+    time_control,
+};
+
+/// Snapshot of the schedules of the app and its render sub app. Schedules can't be reached
+/// from the world, so this is taken while building the app.
+#[derive(Serialize, Schema, Debug, Clone, Default)]
+pub(crate) struct ScheduleGraph {
+    stages: Vec<ScheduleStage>,
+    /// Ordering constraints between systems of the same stage.
+    edges: Vec<ScheduleEdge>,
+}
+
+#[derive(Serialize, Schema, Debug, Clone)]
+pub(crate) struct ScheduleStage {
+    id: String,
+    name: String,
+    /// `main` or `render`.
+    app: String,
+    systems: Vec<ScheduleSystem>,
+    /// Unordered systems that access the same data. Only known for stages moved by
+    /// `finish_devtools` once they ran, as bevy initializes system access on the first run.
+    ambiguities: Vec<SystemAmbiguity>,
+}
+
+#[derive(Serialize, Schema, Debug, Clone)]
+pub(crate) struct ScheduleSystem {
+    id: String,
+    name: String,
+    labels: Vec<String>,
+    before: Vec<String>,
+    after: Vec<String>,
+    /// Label of the run criteria, `unlabeled` for criteria without a label.
+    run_criteria: Option<String>,
+    /// `at_start`, `before_commands` or `at_end` for exclusive systems.
+    exclusive: Option<String>,
+    ambiguity_sets: Vec<String>,
+}
+
+#[derive(Serialize, Schema, Debug, Clone, PartialEq)]
+pub(crate) struct SystemAmbiguity {
+    /// `parallel`, `at_start`, `before_commands` or `at_end`.
+    kind: String,
+    system_a: String,
+    system_b: String,
+    /// Components and resources both systems access with at least one of them writing. Empty
+    /// if the systems conflict through exclusive world access.
+    conflicts: Vec<String>,
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct StageAmbiguities {
+    stage: String,
+    ambiguities: Vec<SystemAmbiguity>,
+}
+
+/// Names of the components and resources a parallel system accesses.
+#[derive(Debug, Default)]
+struct SystemAccess {
+    /// Only read, written data is in `writes`.
+    reads: Vec<String>,
+    writes: Vec<String>,
+    reads_all: bool,
+}
+
+#[derive(Serialize, Schema, Debug, Clone)]
+pub(crate) struct ScheduleEdge {
+    source: String,
+    sink: String,
+}
+
+impl ScheduleGraph {
+    /// Replaces the stage with the id of the single stage of `graph`, keeping its position.
+    fn replace_stage(&mut self, mut graph: ScheduleGraph) {
+        let stage = match graph.stages.pop() {
+            Some(stage) => stage,
+            None => return,
+        };
+        let prefix = format!("{}/", stage.id);
+        self.edges.retain(|edge| !edge.source.starts_with(&prefix));
+        self.edges.extend(graph.edges);
+        match self.stages.iter_mut().find(|other| other.id == stage.id) {
+            Some(other) => *other = stage,
+            None => self.stages.push(stage),
+        }
+    }
+}
+
+/// Lets the devtools show systems that are added after `RemoteDevToolsPlugin`.
+pub trait RemoteDevToolsAppExt {
+    /// Call it last, right before `run`, once all plugins, stages and systems were added.
+    /// Captures the schedule like `capture_devtools_schedule` and lets the app be paused,
+    /// which moves every stage except the devtools stages into a new stage with a
+    /// single system. Stages and systems added afterwards are not paused.
+    ///
+    /// The moved stages are described again after they first ran, when bevy knows what their
+    /// systems access, which adds their execution order ambiguities.
+    fn finish_devtools(&mut self) -> &mut Self;
+
+    /// Updates the schedule shown in the devtools. Does nothing after `finish_devtools`.
+    fn capture_devtools_schedule(&mut self) -> &mut Self;
+}
+
+impl RemoteDevToolsAppExt for App {
+    fn finish_devtools(&mut self) -> &mut Self {
+        self.capture_devtools_schedule();
+        time_control::make_stages_pausable(self);
+        self
+    }
+
+    fn capture_devtools_schedule(&mut self) -> &mut Self {
+        // The schedule only shows the stages that run the moved stages anymore.
+        if time_control::stages_pausable(&self.world) {
+            return self;
+        }
+        let mut graph = ScheduleGraph::default();
+        build_schedule_graph(&mut graph, "main", "", &self.schedule);
+        build_schedule_graph(&mut graph, "render", "", &self.sub_app(RenderApp).schedule);
+        self.insert_resource(graph);
+        self
+    }
+}
+
+/// Describes a stage of the main app again once it ran, now with its ambiguities.
+pub(crate) fn capture_initialized_stage(world: &mut World, name: &str, stage: &SystemStage) {
+    let mut described = ScheduleGraph::default();
+    describe_stage(&mut described, "main", name.to_string(), stage, Some(world));
+    if let Some(mut graph) = world.get_resource_mut::<ScheduleGraph>() {
+        graph.replace_stage(described);
+    }
+}
+
+fn build_schedule_graph(graph: &mut ScheduleGraph, app: &str, prefix: &str, schedule: &Schedule) {
+    let labels = schedule
+        .iter_stages()
+        .map(|(label, _)| label.dyn_clone())
+        .collect::<Vec<Box<dyn StageLabel>>>();
+
+    for label in labels {
+        let name = format!("{}{}", prefix, label_name(&label));
+        // Nested schedules like startup are flattened into their stages.
+        if let Some(nested) = schedule.get_stage::<Schedule>(&*label) {
+            build_schedule_graph(graph, app, &format!("{}/", name), nested);
+            continue;
+        }
+        if let Some(stage) = schedule.get_stage::<SystemStage>(&*label) {
+            describe_stage(graph, app, name, stage, None);
+        }
+    }
+}
+
+/// Adds `stage` to `graph`. With a `world` the systems are expected to be initialized, their
+/// access is then used to find ambiguities.
+fn describe_stage(
+    graph: &mut ScheduleGraph,
+    app: &str,
+    name: String,
+    stage: &SystemStage,
+    world: Option<&World>,
+) {
+    let id = format!("{}/{}", app, name);
+    let mut systems = Vec::new();
+    describe_systems(
+        &mut systems,
+        &id,
+        stage.exclusive_at_start_systems(),
+        Some("at_start"),
+    );
+    describe_systems(&mut systems, &id, stage.parallel_systems(), None);
+    describe_systems(
+        &mut systems,
+        &id,
+        stage.exclusive_before_commands_systems(),
+        Some("before_commands"),
+    );
+    describe_systems(
+        &mut systems,
+        &id,
+        stage.exclusive_at_end_systems(),
+        Some("at_end"),
+    );
+
+    for sink in &systems {
+        for source in &systems {
+            if is_ordered_before(source, sink) {
+                graph.edges.push(ScheduleEdge {
+                    source: source.id.clone(),
+                    sink: sink.id.clone(),
+                });
+            }
+        }
+    }
+
+    let ambiguities = match world {
+        Some(world) => {
+            let mut access = Vec::new();
+            describe_access(&mut access, stage.exclusive_at_start_systems(), world);
+            describe_access(&mut access, stage.parallel_systems(), world);
+            describe_access(
+                &mut access,
+                stage.exclusive_before_commands_systems(),
+                world,
+            );
+            describe_access(&mut access, stage.exclusive_at_end_systems(), world);
+            find_ambiguities(&systems, &access)
+        }
+        None => Vec::new(),
+    };
+
+    graph.stages.push(ScheduleStage {
+        id,
+        name,
+        app: app.to_string(),
+        systems,
+        ambiguities,
+    });
+}
+
+fn describe_systems<C: SystemContainer>(
+    output: &mut Vec<ScheduleSystem>,
+    stage_id: &str,
+    systems: &[C],
+    exclusive: Option<&str>,
+) {
+    for system in systems {
+        output.push(ScheduleSystem {
+            id: format!("{}/{}", stage_id, output.len()),
+            name: system.name().to_string(),
+            labels: system.labels().iter().map(|l| label_name(l)).collect(),
+            before: system.before().iter().map(|l| label_name(l)).collect(),
+            after: system.after().iter().map(|l| label_name(l)).collect(),
+            // `run_criteria` is hidden from bevys docs, but it is the only way to tell that a
+            // system has run criteria without a label.
+            run_criteria: system
+                .run_criteria_label()
+                .map(|l| label_name(l))
+                .or_else(|| system.run_criteria().map(|_| "unlabeled".to_string())),
+            exclusive: exclusive.map(|exclusive| exclusive.to_string()),
+            ambiguity_sets: system
+                .ambiguity_sets()
+                .iter()
+                .map(|l| label_name(l))
+                .collect(),
+        });
+    }
+}
+
+fn describe_access<C: SystemContainer>(
+    output: &mut Vec<Option<SystemAccess>>,
+    systems: &[C],
+    world: &World,
+) {
+    let name = |id: ComponentId| match world.components().get_info(id) {
+        Some(info) => info.name().to_string(),
+        None => format!("{:?}", id),
+    };
+    for system in systems {
+        output.push(
+            system
+                .component_access()
+                .map(|access: &Access<ComponentId>| SystemAccess {
+                    reads: access.reads().map(name).collect(),
+                    writes: access.writes().map(name).collect(),
+                    reads_all: access.reads_all(),
+                }),
+        );
+    }
+}
+
+/// Bevy only orders systems of the same kind, so only those are connected.
+fn is_ordered_before(source: &ScheduleSystem, sink: &ScheduleSystem) -> bool {
+    source.exclusive == sink.exclusive
+        && (sink.after.iter().any(|label| source.labels.contains(label))
+            || source
+                .before
+                .iter()
+                .any(|label| sink.labels.contains(label)))
+}
+
+impl SystemAccess {
+    /// Same rules as `Access::get_conflicts`.
+    fn conflicts(&self, other: &SystemAccess) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if self.reads_all {
+            conflicts.extend(other.writes.iter().cloned());
+        }
+        if other.reads_all {
+            conflicts.extend(self.writes.iter().cloned());
+        }
+        for write in &self.writes {
+            if other.reads.contains(write) || other.writes.contains(write) {
+                conflicts.push(write.clone());
+            }
+        }
+        for read in &self.reads {
+            if other.writes.contains(read) {
+                conflicts.push(read.clone());
+            }
+        }
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+}
+
+/// Pairs of systems of the same kind without a direct or indirect order between them that
+/// either access the same data, with one of them writing, or are exclusive. Systems sharing
+/// an ambiguity set are ignored like bevy does. `access` is parallel to `systems`.
+fn find_ambiguities(
+    systems: &[ScheduleSystem],
+    access: &[Option<SystemAccess>],
+) -> Vec<SystemAmbiguity> {
+    // Systems that run after each system, including indirect ones.
+    let mut successors = Vec::with_capacity(systems.len());
+    for start in 0..systems.len() {
+        let mut reached = vec![false; systems.len()];
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            for (next, sink) in systems.iter().enumerate() {
+                if !reached[next] && is_ordered_before(&systems[index], sink) {
+                    reached[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        successors.push(reached);
+    }
+
+    let mut ambiguities = Vec::new();
+    for (a, system_a) in systems.iter().enumerate() {
+        for (b, system_b) in systems.iter().enumerate().skip(a + 1) {
+            if system_a.exclusive != system_b.exclusive
+                || successors[a][b]
+                || successors[b][a]
+                || system_a
+                    .ambiguity_sets
+                    .iter()
+                    .any(|set| system_b.ambiguity_sets.contains(set))
+            {
+                continue;
+            }
+            let conflicts = match (&access[a], &access[b]) {
+                (Some(access_a), Some(access_b)) => {
+                    let conflicts = access_a.conflicts(access_b);
+                    if conflicts.is_empty() {
+                        continue;
+                    }
+                    conflicts
+                }
+                _ => Vec::new(),
+            };
+            ambiguities.push(SystemAmbiguity {
+                kind: system_a
+                    .exclusive
+                    .clone()
+                    .unwrap_or_else(|| "parallel".to_string()),
+                system_a: system_a.name.clone(),
+                system_b: system_b.name.clone(),
+                conflicts,
+            });
+        }
+    }
+    ambiguities
+}
+
+/// Labels only implement `Debug`, string labels are shown without their quotes.
+fn label_name(label: &dyn std::fmt::Debug) -> String {
+    format!("{:?}", label).trim_matches('"').to_string()
+}
+
+#[get("/v1/schedule")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn schedule() -> Result<Json<ScheduleGraph>, Infallible> {
+    let graph = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        world
+            .get_resource::<ScheduleGraph>()
+            .cloned()
+            .unwrap_or_default()
+    })
+    .await;
+    Ok(graph.into())
+}
+
+/// Systems of the same stage that access the same data, with at least one of them writing, and
+/// without an order between them. Only stages moved by `finish_devtools` are checked, once they ran.
+#[get("/v1/schedule/ambiguities")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn schedule_ambiguities() -> Result<Json<Vec<StageAmbiguities>>, Infallible> {
+    let ambiguities = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        let graph = match world.get_resource::<ScheduleGraph>() {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+        graph
+            .stages
+            .iter()
+            .filter(|stage| !stage.ambiguities.is_empty())
+            .map(|stage| StageAmbiguities {
+                stage: stage.id.clone(),
+                ambiguities: stage.ambiguities.clone(),
+            })
+            .collect()
+    })
+    .await;
+    Ok(ambiguities.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(name: &str, labels: &[&str], after: &[&str]) -> ScheduleSystem {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        ScheduleSystem {
+            id: name.to_string(),
+            name: name.to_string(),
+            labels: strings(labels),
+            before: Vec::new(),
+            after: strings(after),
+            run_criteria: None,
+            exclusive: None,
+            ambiguity_sets: Vec::new(),
+        }
+    }
+
+    fn access(reads: &[&str], writes: &[&str]) -> Option<SystemAccess> {
+        Some(SystemAccess {
+            reads: reads.iter().map(|v| v.to_string()).collect(),
+            writes: writes.iter().map(|v| v.to_string()).collect(),
+            reads_all: false,
+        })
+    }
+
+    fn pairs(ambiguities: &[SystemAmbiguity]) -> Vec<(&str, &str, Vec<String>)> {
+        ambiguities
+            .iter()
+            .map(|a| {
+                (
+                    a.system_a.as_str(),
+                    a.system_b.as_str(),
+                    a.conflicts.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[derive(Default)]
+    struct Score(u32);
+
+    fn add_score(mut score: ResMut<Score>) {
+        score.0 += 1;
+    }
+
+    fn add_bonus(mut score: ResMut<Score>) {
+        score.0 += 10;
+    }
+
+    fn reset_score(mut score: ResMut<Score>) {
+        score.0 = 0;
+    }
+
+    fn read_score(_score: Res<Score>) {}
+
+    #[test]
+    fn moved_stages_report_ambiguities_once_they_ran() {
+        let mut app = App::new();
+        app.init_resource::<ScheduleGraph>()
+            .init_resource::<time_control::TimeControl>()
+            .init_resource::<Score>()
+            .add_system(add_score)
+            .add_system(reset_score)
+            .add_system(read_score.after("add").label("read"))
+            .add_system(add_bonus.label("add"));
+        time_control::make_stages_pausable(&mut app);
+        app.update();
+
+        let graph = app.world.get_resource::<ScheduleGraph>().unwrap();
+        let update = graph
+            .stages
+            .iter()
+            .find(|stage| stage.id == "main/Update")
+            .unwrap();
+        assert_eq!(update.systems.len(), 4);
+        let pairs = update
+            .ambiguities
+            .iter()
+            .map(|a| {
+                (
+                    a.system_a.rsplit("::").next(),
+                    a.system_b.rsplit("::").next(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Every pair except the ordered one and the two readers.
+        assert_eq!(pairs.len(), 5);
+        assert!(!pairs
+            .iter()
+            .any(|pair| pair == &(Some("read_score"), Some("add_bonus"))
+                || pair == &(Some("add_bonus"), Some("read_score"))));
+        assert!(update.ambiguities[0].conflicts[0].ends_with("Score"));
+    }
+
+    #[test]
+    fn conflicting_access_is_ambiguous() {
+        let systems = [
+            system("a", &[], &[]),
+            system("b", &[], &[]),
+            system("c", &[], &[]),
+        ];
+        let access = [
+            access(&["Velocity"], &["Transform"]),
+            access(&["Transform"], &[]),
+            access(&["Velocity"], &[]),
+        ];
+        assert_eq!(
+            pairs(&find_ambiguities(&systems, &access)),
+            vec![("a", "b", vec!["Transform".to_string()])]
+        );
+    }
+
+    #[test]
+    fn ordered_systems_are_not_ambiguous() {
+        // c runs after a through b.
+        let systems = [
+            system("a", &["a"], &[]),
+            system("b", &["b"], &["a"]),
+            system("c", &[], &["b"]),
+        ];
+        let access = [
+            access(&[], &["Transform"]),
+            access(&[], &[]),
+            access(&[], &["Transform"]),
+        ];
+        assert!(find_ambiguities(&systems, &access).is_empty());
+    }
+
+    #[test]
+    fn ambiguity_sets_and_kinds_are_respected() {
+        let mut systems = [
+            system("a", &[], &[]),
+            system("b", &[], &[]),
+            system("c", &[], &[]),
+        ];
+        systems[0].ambiguity_sets.push("physics".to_string());
+        systems[1].ambiguity_sets.push("physics".to_string());
+        systems[2].exclusive = Some("at_start".to_string());
+        let access = [
+            access(&[], &["Transform"]),
+            access(&[], &["Transform"]),
+            None,
+        ];
+        assert!(find_ambiguities(&systems, &access).is_empty());
+    }
+
+    #[test]
+    fn exclusive_systems_conflict_without_access() {
+        let mut systems = [system("a", &[], &[]), system("b", &[], &[])];
+        for system in &mut systems {
+            system.exclusive = Some("at_end".to_string());
+        }
+        let ambiguities = find_ambiguities(&systems, &[None, None]);
+        assert_eq!(pairs(&ambiguities), vec![("a", "b", Vec::new())]);
+        assert_eq!(ambiguities[0].kind, "at_end");
+    }
+
+    #[test]
+    fn reads_all_conflicts_with_writes() {
+        let all = Some(SystemAccess {
+            reads_all: true,
+            ..Default::default()
+        });
+        let writes = access(&[], &["Time"]);
+        assert_eq!(
+            all.as_ref().unwrap().conflicts(writes.as_ref().unwrap()),
+            vec!["Time"]
+        );
+        assert!(all
+            .as_ref()
+            .unwrap()
+            .conflicts(all.as_ref().unwrap())
+            .is_empty());
+    }
+}
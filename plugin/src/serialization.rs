@@ -1,4 +1,5 @@
 use bevy::asset::{AssetPathId, HandleId};
+use bevy::prelude::{Entity, World};
 use bevy::reflect::{Reflect, Uuid};
 /**
  * Credit mostly to https://github.com/dtolnay
@@ -38,6 +39,26 @@ impl From<StringHandleId> for HandleId {
     }
 }
 
+/// Encodes an entity as `<index>v<generation>`, the way bevy prints entities. The generation
+/// keeps requests from hitting another entity that reused the index in the meantime.
+pub fn entity_to_string(entity: Entity) -> String {
+    format!("{}v{}", entity.id(), entity.generation())
+}
+
+/// Resolves an entity id produced by [`entity_to_string`]. A plain index, as used by bevys scene
+/// format and so `/v1/world`, resolves to the entity that currently uses that index.
+pub fn entity_from_string(world: &World, id: &str) -> Option<Entity> {
+    let (index, generation) = match id.split_once('v') {
+        Some((index, generation)) => (index, Some(str::parse::<u32>(generation).ok()?)),
+        None => (id, None),
+    };
+    let entity = world.entities().resolve_from_id(str::parse(index).ok()?)?;
+    match generation {
+        Some(generation) if generation != entity.generation() => None,
+        _ => Some(entity),
+    }
+}
+
 pub struct NumberToStringSerializer<S>(pub S);
 
 impl<S: Serializer> Serializer for NumberToStringSerializer<S> {
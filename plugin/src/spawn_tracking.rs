@@ -0,0 +1,93 @@
+use std::panic::Location;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+use crate::{tracing_tracking::current_system_name, DevInfo};
+
+/// Spawns entities with a `DevInfo` that knows where they were spawned.
+pub trait TrackedCommandsExt<'w, 's> {
+    /// Spawns an empty entity named `name` and records the calling location, as well as the
+    /// spawning system if enabled on the plugin.
+    fn spawn_tracked<'a>(&'a mut self, name: &str) -> EntityCommands<'w, 's, 'a>;
+}
+
+impl<'w, 's> TrackedCommandsExt<'w, 's> for Commands<'w, 's> {
+    #[track_caller]
+    fn spawn_tracked<'a>(&'a mut self, name: &str) -> EntityCommands<'w, 's, 'a> {
+        let dev_info = tracked_dev_info(name, Location::caller());
+        let mut entity = self.spawn();
+        entity.insert(dev_info);
+        entity
+    }
+}
+
+/// Adds a `DevInfo` that knows where it was inserted to already spawned entities, for example
+/// `commands.spawn_bundle(...).insert_tracked("Player")`.
+pub trait TrackedEntityCommandsExt {
+    fn insert_tracked(&mut self, name: &str) -> &mut Self;
+}
+
+impl<'w, 's, 'a> TrackedEntityCommandsExt for EntityCommands<'w, 's, 'a> {
+    #[track_caller]
+    fn insert_tracked(&mut self, name: &str) -> &mut Self {
+        let dev_info = tracked_dev_info(name, Location::caller());
+        self.insert(dev_info)
+    }
+}
+
+fn tracked_dev_info(name: &str, location: &Location) -> DevInfo {
+    let system = current_system_name().unwrap_or_default();
+    DevInfo {
+        name: name.to_string(),
+        module: module_of(&system, location.file()),
+        file: location.file().to_string(),
+        line: location.line(),
+        column: location.column(),
+        system,
+    }
+}
+
+/// Module of the spawning code. Systems are named after the path of their function, without
+/// a known system the module is approximated from the file, `src/world/mod.rs` becomes `world`.
+fn module_of(system: &str, file: &str) -> String {
+    let function = system
+        .split('<')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches("::{{closure}}");
+    if let Some((module, _)) = function.rsplit_once("::") {
+        return module.to_string();
+    }
+    let path = match file.rsplit_once("src") {
+        Some((_, path)) => path,
+        None => file,
+    };
+    path.trim_end_matches(".rs")
+        .split(['/', '\\'])
+        .filter(|part| !matches!(*part, "" | "lib" | "main" | "mod"))
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_is_taken_from_the_system_or_the_file() {
+        assert_eq!(
+            module_of("game::player::spawn", "src/player.rs"),
+            "game::player"
+        );
+        assert_eq!(
+            module_of("game::spawn_all<game::Player>", "src/lib.rs"),
+            "game"
+        );
+        assert_eq!(module_of("", "src/world/mod.rs"), "world");
+        assert_eq!(
+            module_of("", "src\\world\\lifecycle.rs"),
+            "world::lifecycle"
+        );
+        assert_eq!(module_of("game::setup::{{closure}}", "src/lib.rs"), "game");
+    }
+}
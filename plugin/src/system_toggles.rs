@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, convert::Infallible};
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use rweb::{reject::custom, reject::Reject, *};
+use serde::{Deserialize, Serialize};
+
+use crate::sync::{execute_in_world, ExecutionChannel};
+
+/// Systems that can be turned off from the devtools, by the name given to `toggleable`.
+#[derive(Default)]
+pub struct SystemToggles {
+    enabled: BTreeMap<String, bool>,
+}
+
+impl SystemToggles {
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.get(name).copied().unwrap_or(true)
+    }
+}
+
+/// Run criteria that lets a system be turned on and off from the devtools. Systems sharing a
+/// name are toggled together.
+///
+/// ```ignore
+/// app.add_system(physics.with_run_criteria(toggleable("physics")));
+/// ```
+pub fn toggleable(
+    name: impl Into<String>,
+) -> impl FnMut(ResMut<SystemToggles>) -> ShouldRun + Send + Sync + 'static {
+    let name = name.into();
+    move |mut toggles: ResMut<SystemToggles>| {
+        let enabled = match toggles.enabled.get(&name) {
+            Some(enabled) => *enabled,
+            // Systems are listed once they were considered to run the first time.
+            None => *toggles.enabled.entry(name.clone()).or_insert(true),
+        };
+        if enabled {
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug)]
+pub(crate) struct SystemToggle {
+    name: String,
+    enabled: bool,
+}
+
+#[derive(Debug)]
+enum SystemToggleErrors {
+    UnknownSystem,
+}
+impl Reject for SystemToggleErrors {}
+
+#[get("/v1/systems/toggles")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn system_toggles() -> Result<Json<Vec<SystemToggle>>, Infallible> {
+    let toggles = execute_in_world(ExecutionChannel::FrameStart, |world| {
+        list_toggles(world.get_resource::<SystemToggles>().unwrap())
+    })
+    .await;
+    Ok(toggles.into())
+}
+
+#[post("/v1/systems/toggles")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn set_system_toggle(
+    #[json] toggle: SystemToggle,
+) -> Result<Json<Vec<SystemToggle>>, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameStart, move |world| {
+        let mut toggles = world.get_resource_mut::<SystemToggles>().unwrap();
+        match toggles.enabled.get_mut(&toggle.name) {
+            Some(enabled) => {
+                *enabled = toggle.enabled;
+                Ok(list_toggles(&toggles))
+            }
+            None => Err(SystemToggleErrors::UnknownSystem),
+        }
+    })
+    .await
+    .map(Json::from)
+    .map_err(custom)
+}
+
+fn list_toggles(toggles: &SystemToggles) -> Vec<SystemToggle> {
+    toggles
+        .enabled
+        .iter()
+        .map(|(name, enabled)| SystemToggle {
+            name: name.clone(),
+            enabled: *enabled,
+        })
+        .collect()
+}
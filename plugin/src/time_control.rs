@@ -0,0 +1,399 @@
+use std::{borrow::Cow, convert::Infallible, time::Duration};
+
+use bevy::{
+    ecs::{
+        schedule::{ExclusiveSystemDescriptorCoercion, ShouldRun, Stage, StageLabel},
+        system::ExclusiveSystem,
+    },
+    prelude::*,
+    utils::Instant,
+};
+use rweb::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    schedule::capture_initialized_stage,
+    sync::{execute_in_world, ExecutionChannel},
+};
+
+/// Pause state of the app. Changes are made at the start of a frame so a frame never runs
+/// only partially.
+pub(crate) struct TimeControl {
+    paused: bool,
+    pending_steps: u32,
+    run_frame: bool,
+    scale: f32,
+    last_frame: Option<Instant>,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            pending_steps: 0,
+            run_frame: true,
+            scale: 1.0,
+            last_frame: None,
+        }
+    }
+}
+
+const MIN_TIME_SCALE: f32 = 0.1;
+const MAX_TIME_SCALE: f32 = 10.0;
+
+/// Frame time scaled by the time scale set in the devtools. Bevys `Time` can't be changed from
+/// outside, so systems that should follow the time scale need to use this instead.
+///
+/// Paused frames don't advance it and the first frame after a pause only counts as a single
+/// frame.
+pub struct ScaledTime {
+    delta: Duration,
+    time_since_startup: Duration,
+    scale: f32,
+}
+
+impl Default for ScaledTime {
+    fn default() -> Self {
+        Self {
+            delta: Duration::ZERO,
+            time_since_startup: Duration::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+impl ScaledTime {
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn time_since_startup(&self) -> Duration {
+        self.time_since_startup
+    }
+
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.time_since_startup.as_secs_f64()
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct TimeControlState {
+    paused: bool,
+    /// Frames that are still going to run before the app pauses again.
+    pending_steps: u32,
+    scale: f32,
+}
+
+impl From<&TimeControl> for TimeControlState {
+    fn from(control: &TimeControl) -> Self {
+        Self {
+            paused: control.paused,
+            pending_steps: control.pending_steps,
+            scale: control.scale,
+        }
+    }
+}
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct SetTimeScale {
+    /// Clamped to `0.1..=10.0`.
+    scale: f32,
+}
+
+/// Marks an app whose stages were made pausable already.
+struct PausableStages;
+
+pub(crate) fn stages_pausable(world: &World) -> bool {
+    world.contains_resource::<PausableStages>()
+}
+
+/// Lets every stage of the main schedule except the devtools stages be skipped while the app is
+/// paused. bevy can only replace the run criteria of a stage, so each stage is moved into a
+/// `PausedStage` of a new stage that only runs while the app isn't paused. The moved stage
+/// keeps its own run criteria, like a `FixedTimestep` or a state.
+pub(crate) fn make_stages_pausable(app: &mut App) {
+    if stages_pausable(&app.world) {
+        return;
+    }
+    app.insert_resource(PausableStages);
+
+    let labels = app
+        .schedule
+        .iter_stages()
+        .map(|(label, _)| label.dyn_clone())
+        .collect::<Vec<Box<dyn StageLabel>>>();
+    for label in labels {
+        let name = format!("{:?}", label).trim_matches('"').to_string();
+        if name == "devtools_begin" || name == "devtools_end" {
+            continue;
+        }
+        // Nested schedules like startup only run once.
+        let stage = match app.schedule.get_stage_mut::<SystemStage>(&*label) {
+            Some(stage) => stage,
+            None => continue,
+        };
+        let paused = PausedStage {
+            name,
+            stage: std::mem::replace(stage, SystemStage::single_threaded()),
+            captured: false,
+        };
+        stage.set_run_criteria(run_unless_paused);
+        stage.add_system(paused.at_start());
+    }
+}
+
+/// Runs a stage that was moved out of the schedule by `make_stages_pausable`.
+struct PausedStage {
+    name: String,
+    stage: SystemStage,
+    /// Whether the schedule graph was updated after the first run.
+    captured: bool,
+}
+
+impl ExclusiveSystem for PausedStage {
+    fn name(&self) -> Cow<'static, str> {
+        self.name.clone().into()
+    }
+
+    fn run(&mut self, world: &mut World) {
+        self.stage.run(world);
+        if !self.captured {
+            self.captured = true;
+            capture_initialized_stage(world, &self.name, &self.stage);
+        }
+    }
+
+    fn initialize(&mut self, _world: &mut World) {}
+
+    // The systems of the stage check their change ticks when it runs.
+    fn check_change_tick(&mut self, _change_tick: u32) {}
+}
+
+/// Decides at the start of each frame whether the rest of it runs and advances `ScaledTime`.
+pub(crate) fn update_time_control(
+    mut control: ResMut<TimeControl>,
+    mut scaled_time: ResMut<ScaledTime>,
+) {
+    control.run_frame = if !control.paused {
+        true
+    } else if control.pending_steps > 0 {
+        control.pending_steps -= 1;
+        true
+    } else {
+        false
+    };
+
+    let now = Instant::now();
+    let real_delta = match control.last_frame {
+        Some(last_frame) => now - last_frame,
+        None => Duration::ZERO,
+    };
+    control.last_frame = Some(now);
+
+    let delta = if control.run_frame {
+        real_delta.mul_f32(control.scale)
+    } else {
+        Duration::ZERO
+    };
+    scaled_time.scale = control.scale;
+    scaled_time.delta = delta;
+    scaled_time.time_since_startup += delta;
+}
+
+fn run_unless_paused(control: Res<TimeControl>) -> ShouldRun {
+    if control.run_frame {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+#[get("/v1/time")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn time_control() -> Result<Json<TimeControlState>, Infallible> {
+    let state = execute_in_world(ExecutionChannel::FrameStart, |world| {
+        TimeControlState::from(world.get_resource::<TimeControl>().unwrap())
+    })
+    .await;
+    Ok(state.into())
+}
+
+#[post("/v1/time/pause")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn pause() -> Result<Json<TimeControlState>, Infallible> {
+    let state = execute_in_world(ExecutionChannel::FrameStart, |world| {
+        let mut control = world.get_resource_mut::<TimeControl>().unwrap();
+        control.paused = true;
+        control.pending_steps = 0;
+        TimeControlState::from(&*control)
+    })
+    .await;
+    Ok(state.into())
+}
+
+#[post("/v1/time/resume")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn resume() -> Result<Json<TimeControlState>, Infallible> {
+    let state = execute_in_world(ExecutionChannel::FrameStart, |world| {
+        let mut control = world.get_resource_mut::<TimeControl>().unwrap();
+        control.paused = false;
+        control.pending_steps = 0;
+        TimeControlState::from(&*control)
+    })
+    .await;
+    Ok(state.into())
+}
+
+/// Pauses the app if it is running and advances it by `n` frames.
+#[post("/v1/time/step/{n}")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn step(n: u32) -> Result<Json<TimeControlState>, Infallible> {
+    let state = execute_in_world(ExecutionChannel::FrameStart, move |world| {
+        let mut control = world.get_resource_mut::<TimeControl>().unwrap();
+        if !control.paused {
+            control.paused = true;
+            control.pending_steps = 0;
+        }
+        control.pending_steps = control.pending_steps.saturating_add(n);
+        TimeControlState::from(&*control)
+    })
+    .await;
+    Ok(state.into())
+}
+
+#[post("/v1/time/scale")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn set_time_scale(
+    #[json] time_scale: SetTimeScale,
+) -> Result<Json<TimeControlState>, Infallible> {
+    let state = execute_in_world(ExecutionChannel::FrameStart, move |world| {
+        let mut control = world.get_resource_mut::<TimeControl>().unwrap();
+        control.scale = time_scale.scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        TimeControlState::from(&*control)
+    })
+    .await;
+    Ok(state.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Runs(u32);
+
+    #[derive(Default)]
+    struct OtherRuns(u32);
+
+    fn count(mut runs: ResMut<Runs>) {
+        runs.0 += 1;
+    }
+
+    fn count_other(mut runs: ResMut<OtherRuns>) {
+        runs.0 += 1;
+    }
+
+    fn every_other_frame(mut ran: Local<bool>) -> ShouldRun {
+        *ran = !*ran;
+        if *ran {
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<TimeControl>()
+            .init_resource::<ScaledTime>()
+            .init_resource::<Runs>()
+            .init_resource::<OtherRuns>()
+            .add_stage_before(
+                CoreStage::First,
+                "devtools_begin",
+                SystemStage::single_threaded(),
+            )
+            .add_system_to_stage("devtools_begin", update_time_control)
+            .add_system(count)
+            .add_stage_after(
+                CoreStage::Update,
+                "every_other",
+                SystemStage::single_threaded()
+                    .with_run_criteria(every_other_frame)
+                    .with_system(count_other),
+            );
+        make_stages_pausable(&mut app);
+        app
+    }
+
+    fn runs(app: &App) -> u32 {
+        app.world.get_resource::<Runs>().unwrap().0
+    }
+
+    #[test]
+    fn paused_stages_are_skipped() {
+        let mut app = app();
+        app.update();
+        assert_eq!(runs(&app), 1);
+
+        app.world.get_resource_mut::<TimeControl>().unwrap().paused = true;
+        app.update();
+        app.update();
+        assert_eq!(runs(&app), 1);
+        assert_eq!(
+            app.world.get_resource::<ScaledTime>().unwrap().delta(),
+            Duration::ZERO
+        );
+
+        app.world.get_resource_mut::<TimeControl>().unwrap().paused = false;
+        app.update();
+        assert_eq!(runs(&app), 2);
+    }
+
+    #[test]
+    fn steps_run_exactly_n_frames() {
+        let mut app = app();
+        {
+            let mut control = app.world.get_resource_mut::<TimeControl>().unwrap();
+            control.paused = true;
+            control.pending_steps = 2;
+        }
+        for _ in 0..4 {
+            app.update();
+        }
+        assert_eq!(runs(&app), 2);
+        assert_eq!(
+            app.world
+                .get_resource::<TimeControl>()
+                .unwrap()
+                .pending_steps,
+            0
+        );
+    }
+
+    #[test]
+    fn stages_keep_their_run_criteria() {
+        let mut app = app();
+        for _ in 0..4 {
+            app.update();
+        }
+        assert_eq!(runs(&app), 4);
+        assert_eq!(app.world.get_resource::<OtherRuns>().unwrap().0, 2);
+    }
+
+    #[test]
+    fn making_stages_pausable_twice_keeps_one_wrapper() {
+        let mut app = app();
+        make_stages_pausable(&mut app);
+        app.update();
+        assert_eq!(runs(&app), 1);
+    }
+}
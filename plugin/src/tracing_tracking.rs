@@ -1,20 +1,56 @@
-use bevy::{log::LogSettings, prelude::*, utils::tracing::subscriber::set_global_default};
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    log::LogSettings,
+    prelude::*,
+    utils::{tracing::subscriber::set_global_default, Instant},
+};
+use chrono::Utc;
 use tracing_subscriber::{prelude::*, EnvFilter, Registry};
 
-use rweb::*;
-use std::convert::Infallible;
+use rweb::{
+    filters::BoxedFilter,
+    http::header::{HeaderValue, CONTENT_TYPE},
+    hyper::{body::Bytes, Body},
+    reject::{custom, Reject},
+    reply::Response,
+    *,
+};
+use serde::Deserialize;
+use std::{convert::Infallible, fs::File, io::BufWriter, time::Duration};
+use tokio::sync::mpsc::Receiver;
 
+mod captures;
 mod chrome;
 mod events;
+mod filter;
+mod perfetto;
+mod profiler;
+mod systems;
+mod triggers;
 
-pub(crate) use chrome::ChromeLayerController;
+pub(crate) use captures::{captures, delete_capture, download_capture};
+pub(crate) use chrome::{CaptureSink, ChromeLayerController, TraceFormat};
+pub use chrome::{TraceItem, TraceNameFn, TracingSettings};
 pub(crate) use events::*;
+pub(crate) use filter::CaptureFilter;
+pub(crate) use profiler::merge_samples as merge_profiler_samples;
+pub(crate) use systems::{current_system_name, RECORD_SYSTEM_NAMES};
+pub(crate) use triggers::enable as enable_trace_triggers;
+pub use triggers::{DevTools, TraceTriggers};
 
-use crate::sync::{execute_in_world, ExecutionChannel};
+use crate::{
+    api::{app_name, app_version},
+    sync::{execute_in_world, ExecutionChannel},
+};
 
-use self::chrome::ChromeLayer;
+use self::{
+    captures::CaptureMetadata,
+    chrome::ChromeLayer,
+    profiler::{profiler_summary, ProfilerLayer, ProfilerQuery, ProfilerSummary},
+    systems::SystemNameLayer,
+};
 
-pub fn init(app: &mut App) {
+pub fn init(app: &mut App, settings: TracingSettings) {
     let default_filter = {
         let settings = app.world.get_resource_or_insert_with(LogSettings::default);
         format!("{},{}", settings.level, settings.filter)
@@ -27,40 +63,183 @@ pub fn init(app: &mut App) {
     let fmt_layer = tracing_subscriber::fmt::Layer::default();
     let subscriber = subscriber.with(fmt_layer);
     let subscriber = subscriber.with(EventLayer::new());
-    let subscriber = subscriber.with(ChromeLayer::new());
+    let subscriber = subscriber.with(ChromeLayer::new(settings));
+    let subscriber = subscriber.with(SystemNameLayer::new());
+    let subscriber = subscriber.with(ProfilerLayer::new());
 
     set_global_default(subscriber)
         .expect("Could not set global default tracing subscriber. If you've already set up a tracing subscriber, please disable LogPlugin from Bevy's DefaultPlugins");
 }
 
-#[get("/v1/tracing/frames/{n}")]
-#[cors(origins("*"), headers("content-type"))]
-pub(crate) async fn trace_frames(n: usize) -> Result<String, Infallible> {
-    let mut output = String::with_capacity(n * 10 * 1024 * 1024);
-    output += "[";
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct CaptureOptions {
+    /// Saves the capture to the capture directory and responds with its metadata.
+    save: bool,
+    format: TraceFormat,
+    /// Comma separated target prefixes of recorded spans and events.
+    targets: Option<String>,
+    /// Comma separated names of recorded spans.
+    spans: Option<String>,
+    /// Comma separated name prefixes of recorded threads.
+    threads: Option<String>,
+    /// Duration in milliseconds below which spans are dropped.
+    min_duration: Option<f64>,
+}
+
+impl CaptureOptions {
+    fn filter(&self) -> CaptureFilter {
+        CaptureFilter::new(
+            self.targets.as_deref(),
+            self.spans.as_deref(),
+            self.threads.as_deref(),
+            self.min_duration,
+        )
+    }
+}
+
+/// Amount of chunks a streamed response buffers before its producer waits for the client.
+const STREAM_BUFFER_CHUNKS: usize = 8;
+
+/// Captures the next `n` frames as Chrome trace JSON, an array with one array of trace events
+/// per frame, or as perfetto protobuf with `?format=perfetto`. The capture is streamed to the
+/// response while the frames run. Spans and events that start while the client is behind are
+/// dropped instead of buffered, frames they are missing from get a `truncated` instant event
+/// with the number of dropped items. Spans and events can be narrowed down with the filter parameters of
+/// `CaptureOptions`. Every frame is wrapped in a `frame N` span on its own track and ends with
+/// counters of the world size and frame rate.
+pub(crate) fn trace_frames() -> BoxedFilter<(impl Reply,)> {
+    warp::path!("v1" / "tracing" / "frames" / usize)
+        .and(warp::get())
+        .and(warp::query::<CaptureOptions>())
+        .and_then(capture_frames)
+        .boxed()
+}
+
+async fn capture_frames(n: usize, options: CaptureOptions) -> Result<Response, Rejection> {
+    let format = options.format;
+    let filter = options.filter();
+    if n == 0 {
+        return Ok(match format {
+            TraceFormat::Chrome => warp::reply::json(&Vec::<()>::new()).into_response(),
+            TraceFormat::Perfetto => Response::new(Body::empty()),
+        });
+    }
+
+    if options.save {
+        let dir = captures::capture_dir().await?;
+        let id = captures::new_capture_id();
+        let path = captures::capture_path(&dir, &id, format);
+        let file = File::create(&path).map_err(|_| custom(TracingErrors::CannotCreateFile))?;
+        let timestamp = Utc::now();
+        let sink = CaptureSink::File(BufWriter::new(file));
+        let duration = capture_frames_into(sink, format, filter, n).await;
+        let (app, version) = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+            (app_name(world), app_version(world))
+        })
+        .await;
 
+        let metadata = CaptureMetadata {
+            id,
+            app,
+            version,
+            frames: n,
+            format,
+            timestamp,
+            duration: duration.as_secs_f64() * 1000.0,
+            bytes: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            reason: None,
+        };
+        captures::write_metadata(&dir, &metadata)?;
+        return Ok(warp::reply::json(&metadata).into_response());
+    }
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+    tokio::spawn(capture_frames_into(
+        CaptureSink::Stream(sender),
+        format,
+        filter,
+        n,
+    ));
+    Ok(stream_response(receiver, format))
+}
+
+/// Response with a trace in `format` whose body is streamed from `receiver` until its sender
+/// is dropped.
+fn stream_response<T: Into<Bytes> + Send + 'static>(
+    receiver: Receiver<T>,
+    format: TraceFormat,
+) -> Response {
+    let chunks = futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|chunk| (Ok::<_, Infallible>(chunk), receiver))
+    });
+
+    let mut response = Response::new(Body::wrap_stream(chunks));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    response
+}
+
+/// Captures `n` frames into `sink` and returns how long they took.
+async fn capture_frames_into(
+    sink: CaptureSink,
+    format: TraceFormat,
+    filter: CaptureFilter,
+    n: usize,
+) -> Duration {
     // Wait for the next frame start and start tracing.
-    let _ = execute_in_world(ExecutionChannel::FrameStart, |_| {
-        ChromeLayerController::start()
+    execute_in_world(ExecutionChannel::FrameStart, move |_| {
+        ChromeLayerController::start(sink, format, filter)
     })
     .await;
+    let started = Instant::now();
 
     for i in 0..n {
         // Wait for n frame ends.
-        let future_output = execute_in_world(ExecutionChannel::FrameEnd, move |_| {
-            ChromeLayerController::stop(i != n - 1)
+        execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+            ChromeLayerController::end_frame(i == n - 1, frame_counters(world))
         })
+        .await
         .await;
-        output += future_output.await.as_str();
-        output += ",\n";
     }
+    started.elapsed()
+}
 
-    output.pop();
-    output.pop();
-    output += "]";
+/// Values recorded as counters at the end of every captured frame.
+fn frame_counters(world: &World) -> Vec<(&'static str, f64)> {
+    let mut counters = vec![
+        ("entities", world.entities().len() as f64),
+        ("archetypes", world.archetypes().len() as f64),
+    ];
+    if let Some(diagnostics) = world.get_resource::<Diagnostics>() {
+        let fps = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.value());
+        if let Some(fps) = fps {
+            counters.push(("fps", fps));
+        }
+        let frame_time = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+            .and_then(|frame_time| frame_time.value());
+        if let Some(frame_time) = frame_time {
+            counters.push(("frame time (ms)", frame_time * 1000.0));
+        }
+    }
+    counters
+}
 
-    Ok(output)
+#[derive(Debug)]
+enum TracingErrors {
+    CannotCreateFile,
+    NoCaptureDirectory,
+    CaptureNotFound,
 }
+impl Reject for TracingErrors {}
 
 #[get("/v1/tracing/events")]
 #[cors(origins("*"), headers("content-type"))]
@@ -68,3 +247,11 @@ pub(crate) fn get_tracing_events() -> Json<Vec<StoredEvent>> {
     let events = STORED_EVENTS.lock().unwrap();
     events.iter().cloned().collect::<Vec<_>>().into()
 }
+
+/// Durations of all spans over the last seconds, slowest first. Unlike `trace_frames` this is
+/// always recorded.
+#[get("/v1/profiler/summary")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) fn get_profiler_summary(#[query] query: ProfilerQuery) -> Json<ProfilerSummary> {
+    profiler_summary(&query).into()
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use rweb::{
+    filters::BoxedFilter,
+    reject::{custom, Rejection},
+    reply::Response,
+    *,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sync::{execute_in_world, ExecutionChannel};
+
+use super::{
+    chrome::CHUNK_SIZE, stream_response, TraceFormat, TracingErrors, STREAM_BUFFER_CHUNKS,
+};
+
+/// Metadata stored next to a saved capture as `<id>.meta.json`.
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub(crate) struct CaptureMetadata {
+    pub(super) id: String,
+    pub(super) app: String,
+    /// Version of the captured app, see `RemoteDevToolsPlugin::with_app_version`.
+    pub(super) version: String,
+    pub(super) frames: usize,
+    /// Captures saved before formats could be chosen are Chrome traces.
+    #[serde(default)]
+    pub(super) format: TraceFormat,
+    pub(super) timestamp: DateTime<Utc>,
+    /// Wall clock time the captured frames took, in milliseconds.
+    pub(super) duration: f64,
+    pub(super) bytes: u64,
+    /// Why the capture was taken, set for snapshots of `TraceTriggers`.
+    #[serde(default)]
+    pub(super) reason: Option<String>,
+}
+
+pub(super) async fn capture_dir() -> Result<PathBuf, Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        world
+            .get_resource::<crate::DevToolsSettings>()
+            .and_then(|settings| settings.capture_dir.clone())
+    })
+    .await
+    .ok_or_else(|| custom(TracingErrors::NoCaptureDirectory))
+}
+
+pub(super) fn new_capture_id() -> String {
+    Utc::now().format("%Y%m%d-%H%M%S-%3f").to_string()
+}
+
+pub(super) fn capture_path(dir: &Path, id: &str, format: TraceFormat) -> PathBuf {
+    dir.join(format!("{}.{}", id, format.extension()))
+}
+
+fn metadata_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.meta.json", id))
+}
+
+fn read_metadata(dir: &Path, id: &str) -> Result<CaptureMetadata, Rejection> {
+    fs::read_to_string(metadata_path(dir, id))
+        .ok()
+        .and_then(|json| serde_json::from_str::<CaptureMetadata>(&json).ok())
+        .ok_or_else(|| custom(TracingErrors::CaptureNotFound))
+}
+
+pub(super) fn write_metadata(dir: &Path, metadata: &CaptureMetadata) -> Result<(), Rejection> {
+    let json = serde_json::to_string(metadata).unwrap();
+    fs::write(metadata_path(dir, &metadata.id), json)
+        .map_err(|_| custom(TracingErrors::CannotCreateFile))
+}
+
+/// Ids end up in file names, so anything that could leave the capture directory is refused.
+fn validate_id(id: &str) -> Result<(), Rejection> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(custom(TracingErrors::CaptureNotFound))
+    }
+}
+
+/// Saved captures, newest first.
+#[get("/v1/tracing/captures")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn captures() -> Result<Json<Vec<CaptureMetadata>>, Rejection> {
+    let dir = capture_dir().await?;
+    // Nothing was saved yet if the directory is gone.
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new().into()),
+    };
+
+    let mut captures = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".meta.json"))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|json| serde_json::from_str::<CaptureMetadata>(&json).ok())
+        .collect::<Vec<_>>();
+    captures.sort_by_key(|capture| std::cmp::Reverse(capture.timestamp));
+
+    Ok(captures.into())
+}
+
+#[delete("/v1/tracing/captures/{id}")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn delete_capture(id: String) -> Result<Json<CaptureMetadata>, Rejection> {
+    validate_id(&id)?;
+    let dir = capture_dir().await?;
+    let metadata = read_metadata(&dir, &id)?;
+
+    let _ = fs::remove_file(capture_path(&dir, &id, metadata.format));
+    let _ = fs::remove_file(metadata_path(&dir, &id));
+    Ok(metadata.into())
+}
+
+/// Downloads a saved capture, streamed from disk.
+pub(crate) fn download_capture() -> BoxedFilter<(impl Reply,)> {
+    warp::path!("v1" / "tracing" / "captures" / String)
+        .and(warp::get())
+        .and_then(download)
+        .boxed()
+}
+
+async fn download(id: String) -> Result<Response, Rejection> {
+    validate_id(&id)?;
+    let dir = capture_dir().await?;
+    let format = read_metadata(&dir, &id)?.format;
+    let mut file = File::open(capture_path(&dir, &id, format))
+        .map_err(|_| custom(TracingErrors::CaptureNotFound))?;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+    std::thread::spawn(move || loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        match (&mut file).take(CHUNK_SIZE as u64).read_to_end(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                if sender.blocking_send(chunk).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(stream_response(receiver, format))
+}
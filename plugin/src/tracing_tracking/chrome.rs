@@ -1,15 +1,25 @@
 use bevy::utils::tracing;
-use tokio::sync::Notify;
-use tracing::{span, Event, Subscriber};
+use tokio::sync::{mpsc::error::TrySendError, Notify};
+use tracing::{span, Event, Metadata, Subscriber};
 use tracing_subscriber::{
     layer::Context,
     registry::{LookupSpan, SpanRef},
     Layer,
 };
 
+use super::{
+    filter::{CaptureFilter, FilterState},
+    perfetto::PerfettoEncoder,
+    triggers,
+    triggers::RingBuffer,
+};
 use json::{number::Number, object::Object, JsonValue};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
 use std::{
-    fmt::Write,
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write as _},
     marker::PhantomData,
     sync::{
         atomic::{AtomicU64, Ordering},
@@ -22,34 +32,110 @@ use std::cell::RefCell;
 
 lazy_static::lazy_static! {
     static ref GLOBAL_OUT: Mutex<Option<Sender<Message>>> = Mutex::new(None);
-    static ref LAST_TRACING_RESULT: Mutex<Option<String>> = Mutex::new(None);
+}
+
+lazy_static::lazy_static! {
+    /// Time all trace timestamps are relative to.
+    static ref TRACE_START: std::time::Instant = std::time::Instant::now();
+}
+
+static NEXT_FLOW_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Thread id of the track that shows the synthetic `frame N` spans of captures, real threads
+/// are numbered from 1.
+const FRAMES_TID: u64 = 0;
+
+/// Trace timestamp of now, in microseconds.
+fn timestamp() -> f64 {
+    TRACE_START.elapsed().as_nanos() as f64 / 1000.0
+}
+
+/// Size from which written trace events are handed to the sink, even within a frame.
+pub(super) const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Where a running capture writes its trace to, encoded in the `TraceFormat` of the capture.
+pub(crate) enum CaptureSink {
+    /// Chunks of a streamed response, the response ends once the sender is dropped.
+    Stream(tokio::sync::mpsc::Sender<Vec<u8>>),
+    File(BufWriter<File>),
+}
+
+impl CaptureSink {
+    /// Returns false once the receiving end is gone.
+    pub(super) fn write(&mut self, chunk: Vec<u8>) -> bool {
+        match self {
+            CaptureSink::Stream(sender) => sender.blocking_send(chunk).is_ok(),
+            CaptureSink::File(file) => file.write_all(&chunk).is_ok(),
+        }
+    }
+
+    /// Hands `chunk` to the sink without blocking. Gives the chunk back while a stream is full
+    /// and `None` once the receiving end is gone.
+    fn try_write(&mut self, chunk: Vec<u8>) -> Result<(), Option<Vec<u8>>> {
+        match self {
+            CaptureSink::Stream(sender) => sender.try_send(chunk).map_err(|error| match error {
+                TrySendError::Full(chunk) => Some(chunk),
+                TrySendError::Closed(_) => None,
+            }),
+            CaptureSink::File(file) => file.write_all(&chunk).map_err(|_| None),
+        }
+    }
+
+    pub(super) fn finish(self) {
+        if let CaptureSink::File(mut file) = self {
+            let _ = file.flush();
+        }
+    }
 }
 
 pub(crate) struct ChromeLayerController;
 
 impl ChromeLayerController {
-    /// Tells the tracing layer to start tracing and clears all other traces before hands.
-    pub(crate) fn start() {
-        let mut output = GLOBAL_OUT.lock().unwrap();
-        let output = output.as_mut().unwrap();
-        output.send(Message::Start).unwrap();
+    /// Tells the tracing layer to start a capture into `sink`, replacing any running capture.
+    pub(crate) fn start(sink: CaptureSink, format: TraceFormat, filter: CaptureFilter) {
+        Self::send(Message::Start(timestamp(), sink, format, filter));
     }
 
-    /// Stops the active tracing and returns the result of the tracing.
-    pub(crate) async fn stop(immediate_start: bool) -> String {
+    /// Ends the current frame of the capture, which is finished with the `last` frame.
+    /// `counters` are recorded with the end of the frame. Resolves once the frame is handed to
+    /// the sink.
+    pub(crate) async fn end_frame(last: bool, counters: Vec<(&'static str, f64)>) {
         let notify = {
             let mut output = GLOBAL_OUT.lock().unwrap();
             let output = output.as_mut().unwrap();
             let notify = Arc::new(Notify::new());
-            output.send(Message::Stop(notify.clone())).unwrap();
-            if immediate_start {
-                output.send(Message::Start).unwrap();
-            }
+            output
+                .send(Message::EndFrame(
+                    timestamp(),
+                    last,
+                    counters,
+                    notify.clone(),
+                ))
+                .unwrap();
             notify
         };
         notify.notified().await;
-        let mut result_lock = LAST_TRACING_RESULT.lock().unwrap();
-        result_lock.take().unwrap()
+    }
+
+    /// Keeps the last frames in `ring_buffer` from now on.
+    pub(super) fn ring_buffer(ring_buffer: RingBuffer) {
+        Self::send(Message::RingBuffer(ring_buffer));
+    }
+
+    /// Ends the current frame of the ring buffer.
+    pub(super) fn ring_frame() {
+        Self::send(Message::RingFrame);
+    }
+
+    /// Saves the frames of the ring buffer once the current one ended.
+    pub(super) fn snapshot(reason: String) {
+        Self::send(Message::Snapshot(reason));
+    }
+
+    fn send(message: Message) {
+        let mut output = GLOBAL_OUT.lock().unwrap();
+        let output = output.as_mut().unwrap();
+        output.send(message).unwrap();
     }
 }
 
@@ -58,39 +144,138 @@ thread_local! {
     static TID: RefCell<Option<(u64, u64)>> = RefCell::new(None);
 }
 
-type NameFn<S> = Box<dyn Fn(&EventOrSpan<'_, '_, S>) -> String + Send + Sync>;
+/// Computes the name or category a span or event is shown with in traces.
+pub type TraceNameFn = Arc<dyn Fn(&TraceItem<'_>) -> String + Send + Sync>;
+
+/// Span or event whose name or category is computed by a `TraceNameFn`.
+pub struct TraceItem<'a> {
+    pub metadata: &'static Metadata<'static>,
+    /// Fields of the span or event formatted as `name=value` pairs.
+    pub fields: &'a str,
+    pub is_span: bool,
+}
+
+/// How spans and events are recorded in trace captures, see
+/// `RemoteDevToolsPlugin::with_tracing_settings`.
+#[derive(Clone)]
+pub struct TracingSettings {
+    name_fn: Option<TraceNameFn>,
+    cat_fn: Option<TraceNameFn>,
+    include_args: bool,
+    include_locations: bool,
+}
+
+impl Default for TracingSettings {
+    fn default() -> Self {
+        Self {
+            name_fn: None,
+            cat_fn: None,
+            include_args: true,
+            include_locations: true,
+        }
+    }
+}
+
+impl TracingSettings {
+    /// Replaces the name of spans and events, which is the name of their metadata by default.
+    pub fn with_name_fn(
+        mut self,
+        name_fn: impl Fn(&TraceItem<'_>) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.name_fn = Some(Arc::new(name_fn));
+        self
+    }
+
+    /// Replaces the category of spans and events, which is the target of their metadata by
+    /// default.
+    pub fn with_cat_fn(
+        mut self,
+        cat_fn: impl Fn(&TraceItem<'_>) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.cat_fn = Some(Arc::new(cat_fn));
+        self
+    }
+
+    /// Names spans after their name and fields, e.g. `system: name="my_system"`, so bevys
+    /// generic `system` spans can be told apart.
+    pub fn with_fields_in_names(self) -> Self {
+        self.with_name_fn(|item| {
+            if item.is_span && !item.fields.is_empty() {
+                format!("{}: {}", item.metadata.name(), item.fields)
+            } else {
+                item.metadata.name().to_string()
+            }
+        })
+    }
+
+    /// Whether the fields of spans and events are recorded as args.
+    pub fn with_args(mut self, include_args: bool) -> Self {
+        self.include_args = include_args;
+        self
+    }
+
+    /// Whether the source file and line of spans and events are recorded as args.
+    pub fn with_locations(mut self, include_locations: bool) -> Self {
+        self.include_locations = include_locations;
+        self
+    }
+
+    fn needs_fields(&self) -> bool {
+        self.include_args || self.name_fn.is_some() || self.cat_fn.is_some()
+    }
+}
 
 pub struct ChromeLayer<S>
 where
     S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
 {
     out: Arc<Mutex<Sender<Message>>>,
-    start: std::time::Instant,
     max_tid: AtomicU64,
     session: Arc<AtomicU64>,
-    include_args: bool,
-    include_locations: bool,
-    name_fn: Option<NameFn<S>>,
-    cat_fn: Option<NameFn<S>>,
+    settings: TracingSettings,
     _inner: PhantomData<S>,
 }
 
-struct Callsite {
-    tid: u64,
-    name: String,
-    target: String,
-    file: Option<&'static str>,
-    line: Option<u32>,
-    args: Option<Arc<Object>>,
+#[derive(Clone)]
+pub(super) struct Callsite {
+    pub(super) tid: u64,
+    pub(super) name: String,
+    pub(super) target: String,
+    pub(super) file: Option<&'static str>,
+    pub(super) line: Option<u32>,
+    pub(super) args: Option<Arc<Object>>,
 }
 
-enum Message {
-    Enter(f64, Callsite, Option<u64>),
+impl Callsite {
+    /// Callsite for flow events, which carry no args.
+    fn for_flow(&self, tid: u64) -> Callsite {
+        Callsite {
+            tid,
+            name: self.name.clone(),
+            target: self.target.clone(),
+            file: None,
+            line: None,
+            args: None,
+        }
+    }
+}
+
+pub(super) enum Message {
+    Enter(f64, Callsite),
     Event(f64, Callsite),
-    Exit(f64, Callsite, Option<u64>),
+    Exit(f64, Callsite),
     NewThread(u64, String),
-    Start,
-    Stop(Arc<Notify>),
+    /// Start of a flow arrow with its id, bound to the slice enclosing it.
+    FlowStart(f64, Callsite, u64),
+    /// End of a flow arrow with its id, bound to the slice enclosing it.
+    FlowEnd(f64, Callsite, u64),
+    /// Value of a counter, recorded once per frame.
+    Counter(f64, &'static str, f64),
+    Start(f64, CaptureSink, TraceFormat, CaptureFilter),
+    EndFrame(f64, bool, Vec<(&'static str, f64)>, Arc<Notify>),
+    RingBuffer(RingBuffer),
+    RingFrame,
+    Snapshot(String),
 }
 
 pub enum EventOrSpan<'a, 'b, S>
@@ -105,128 +290,25 @@ impl<S> ChromeLayer<S>
 where
     S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
 {
-    pub fn new() -> ChromeLayer<S> {
+    pub fn new(settings: TracingSettings) -> ChromeLayer<S> {
         let (tx, rx) = std::sync::mpsc::channel::<Message>();
         OUT.with(|val| val.replace(Some(tx.clone())));
         let mut global_out = GLOBAL_OUT.lock().unwrap();
         *global_out = Some(tx.clone());
 
         let session = Arc::new(AtomicU64::new(0));
-        let session_clone = session.clone();
+        let mut writer = Writer::new(session.clone());
         let _handle = std::thread::spawn(move || {
-            let mut running = false;
-            let mut write = String::with_capacity(10 * 1024 * 1024);
-
             for msg in rx {
-                match &msg {
-                    Message::Start => {
-                        write.clear();
-                        write.write_str("[").unwrap();
-                        session_clone.fetch_add(1, Ordering::Relaxed);
-                        running = true;
-                        continue;
-                    }
-                    Message::Stop(notify) => {
-                        write.pop(); // JSON doesn't like trailing comma
-                        write.pop();
-                        write.write_str("]").unwrap();
-                        running = false;
-                        let mut output = LAST_TRACING_RESULT.lock().unwrap();
-                        *output = Some(write.clone());
-                        notify.notify_one();
-                        continue;
-                    }
-                    _ => {}
-                }
-
-                if !running {
-                    continue;
-                }
-
-                let mut entry = Object::new();
-
-                let (ph, ts, callsite, id) = match &msg {
-                    Message::Enter(ts, callsite, None) => ("B", Some(ts), Some(callsite), None),
-                    Message::Enter(ts, callsite, Some(root_id)) => {
-                        ("b", Some(ts), Some(callsite), Some(root_id))
-                    }
-                    Message::Event(ts, callsite) => ("i", Some(ts), Some(callsite), None),
-                    Message::Exit(ts, callsite, None) => ("E", Some(ts), Some(callsite), None),
-                    Message::Exit(ts, callsite, Some(root_id)) => {
-                        ("e", Some(ts), Some(callsite), Some(root_id))
-                    }
-                    Message::NewThread(_tid, _name) => ("M", None, None, None),
-                    _ => unreachable!("Start | Stop message is handled earlier."),
-                };
-                entry.insert("ph", ph.to_string().into());
-                entry.insert("pid", 1.into());
-
-                if let Message::NewThread(tid, name) = msg {
-                    entry.insert("name", "thread_name".to_string().into());
-                    entry.insert("tid", tid.into());
-                    let mut args = Object::new();
-                    args.insert("name", name.into());
-                    entry.insert("args", args.into());
-                } else {
-                    let ts = ts.unwrap();
-                    let callsite = callsite.unwrap();
-                    entry.insert("ts", JsonValue::Number(Number::from(*ts)));
-                    entry.insert("name", callsite.name.clone().into());
-                    entry.insert("cat", callsite.target.clone().into());
-                    entry.insert("tid", callsite.tid.into());
-
-                    if let Some(&id) = id {
-                        entry.insert("id", id.into());
-                    }
-
-                    if ph == "i" {
-                        entry.insert("s", "p".into());
-                    }
-
-                    let mut args = Object::new();
-                    if let (Some(file), Some(line)) = (callsite.file, callsite.line) {
-                        args.insert("[file]", file.to_string().into());
-                        args.insert("[line]", line.into());
-                    }
-
-                    if let Some(call_args) = &callsite.args {
-                        for (k, v) in call_args.iter() {
-                            args.insert(k, v.clone());
-                        }
-                    }
-
-                    if !args.is_empty() {
-                        entry.insert("args", args.into());
-                    }
-                }
-
-                write.write_str(entry.dump().as_str()).unwrap();
-                write.write_str(",\n").unwrap();
+                writer.handle(msg);
             }
         });
 
         ChromeLayer {
             out: Arc::new(Mutex::new(tx)),
-            start: std::time::Instant::now(),
-            max_tid: AtomicU64::new(0),
+            max_tid: AtomicU64::new(FRAMES_TID + 1),
             session,
-            /*
-            name_fn: Some(Box::new(|event_or_span| match event_or_span {
-                EventOrSpan::Event(event) => event.metadata().name().into(),
-                EventOrSpan::Span(span) => {
-                    if let Some(fields) = span.extensions().get::<FormattedFields<DefaultFields>>()
-                    {
-                        format!("{}: {}", span.metadata().name(), fields.fields.as_str())
-                    } else {
-                        span.metadata().name().into()
-                    }
-                }
-            })),
-            */
-            name_fn: None,
-            cat_fn: None,
-            include_args: true,
-            include_locations: true,
+            settings,
             _inner: PhantomData::default(),
         }
     }
@@ -252,17 +334,29 @@ where
         })
     }
 
-    fn get_callsite(&self, data: EventOrSpan<S>) -> Callsite {
+    /// Id of the current thread, named in the trace when it is first seen in a session.
+    fn thread_id(&self) -> u64 {
         let (tid, new_thread) = self.get_tid();
-        let name = self.name_fn.as_ref().map(|name_fn| name_fn(&data));
-        let target = self.cat_fn.as_ref().map(|cat_fn| cat_fn(&data));
-        let meta = match data {
-            EventOrSpan::Event(e) => e.metadata(),
-            EventOrSpan::Span(s) => s.metadata(),
+        if new_thread {
+            let name = match std::thread::current().name() {
+                Some(name) => name.to_owned(),
+                None => tid.to_string(),
+            };
+            self.send_message(Message::NewThread(tid, name));
+        }
+        tid
+    }
+
+    fn get_callsite(&self, data: EventOrSpan<S>) -> Callsite {
+        let tid = self.thread_id();
+        let settings = &self.settings;
+        let (meta, is_span) = match data {
+            EventOrSpan::Event(e) => (e.metadata(), false),
+            EventOrSpan::Span(s) => (s.metadata(), true),
         };
-        let args = match data {
+        let fields = match data {
             EventOrSpan::Event(e) => {
-                if self.include_args {
+                if settings.needs_fields() {
                     let mut args = Object::new();
                     e.record(&mut JsonVisitor { object: &mut args });
                     Some(Arc::new(args))
@@ -275,22 +369,29 @@ where
                 .get::<ArgsWrapper>()
                 .map(|e| Arc::clone(&e.args)),
         };
+
+        let (name, target) = if settings.name_fn.is_some() || settings.cat_fn.is_some() {
+            let item = TraceItem {
+                metadata: meta,
+                fields: &format_fields(fields.as_deref()),
+                is_span,
+            };
+            (
+                settings.name_fn.as_ref().map(|name_fn| name_fn(&item)),
+                settings.cat_fn.as_ref().map(|cat_fn| cat_fn(&item)),
+            )
+        } else {
+            (None, None)
+        };
         let name = name.unwrap_or_else(|| meta.name().into());
         let target = target.unwrap_or_else(|| meta.target().into());
-        let (file, line) = if self.include_locations {
+        let args = if settings.include_args { fields } else { None };
+        let (file, line) = if settings.include_locations {
             (meta.file(), meta.line())
         } else {
             (None, None)
         };
 
-        if new_thread {
-            let name = match std::thread::current().name() {
-                Some(name) => name.to_owned(),
-                None => tid.to_string(),
-            };
-            self.send_message(Message::NewThread(tid, name));
-        }
-
         Callsite {
             tid,
             name,
@@ -303,16 +404,35 @@ where
 
     fn enter_span(&self, span: SpanRef<S>, ts: f64) {
         let callsite = self.get_callsite(EventOrSpan::Span(&span));
-        self.send_message(Message::Enter(ts, callsite, None));
+        let tid = callsite.tid;
+        let flow = callsite.for_flow(tid);
+        self.send_message(Message::Enter(ts, callsite));
+
+        // Links spans that run on another thread than they were created on, like task pool
+        // work, to where they were created. Only the first entry is linked.
+        let origin = span.extensions_mut().remove::<SpanOrigin>();
+        if let Some(origin) = origin {
+            if origin.tid != tid {
+                let id = NEXT_FLOW_ID.fetch_add(1, Ordering::Relaxed);
+                let start = flow.for_flow(origin.tid);
+                self.send_message(Message::FlowStart(origin.ts, start, id));
+                self.send_message(Message::FlowEnd(ts, flow, id));
+            }
+        }
+
+        let follows = span.extensions_mut().remove::<PendingFlows>();
+        for (id, start) in follows.into_iter().flat_map(|follows| follows.0) {
+            self.send_message(Message::FlowEnd(ts, start.for_flow(tid), id));
+        }
     }
 
     fn exit_span(&self, span: SpanRef<S>, ts: f64) {
         let callsite = self.get_callsite(EventOrSpan::Span(&span));
-        self.send_message(Message::Exit(ts, callsite, None));
+        self.send_message(Message::Exit(ts, callsite));
     }
 
     fn get_ts(&self) -> f64 {
-        self.start.elapsed().as_nanos() as f64 / 1000.0
+        timestamp()
     }
 
     fn send_message(&self, message: Message) {
@@ -341,6 +461,9 @@ where
         let ts = self.get_ts();
         let callsite = self.get_callsite(EventOrSpan::Event(event));
         self.send_message(Message::Event(ts, callsite));
+        if let Some(reason) = triggers::event_trigger(event.metadata()) {
+            self.send_message(Message::Snapshot(reason));
+        }
     }
 
     fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
@@ -349,8 +472,13 @@ where
     }
 
     fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
-        let _ts = self.get_ts();
-        if self.include_args {
+        let ts = self.get_ts();
+        let tid = self.thread_id();
+        ctx.span(id)
+            .unwrap()
+            .extensions_mut()
+            .insert(SpanOrigin { tid, ts });
+        if self.settings.needs_fields() {
             let mut args = Object::new();
             attrs.record(&mut JsonVisitor { object: &mut args });
             ctx.span(id).unwrap().extensions_mut().insert(ArgsWrapper {
@@ -359,9 +487,325 @@ where
         }
     }
 
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        let ts = self.get_ts();
+        if let (Some(span), Some(follows)) = (ctx.span(id), ctx.span(follows)) {
+            // The flow starts where the relation is recorded and ends once `span` is entered.
+            let callsite = self.get_callsite(EventOrSpan::Span(&follows));
+            let id = NEXT_FLOW_ID.fetch_add(1, Ordering::Relaxed);
+            let flow = callsite.for_flow(callsite.tid);
+
+            let mut extensions = span.extensions_mut();
+            match extensions.get_mut::<PendingFlows>() {
+                Some(pending) => pending.0.push((id, flow.clone())),
+                None => extensions.insert(PendingFlows(vec![(id, flow.clone())])),
+            }
+            self.send_message(Message::FlowStart(ts, flow, id));
+        }
+    }
+
     fn on_close(&self, _id: span::Id, _ctx: Context<'_, S>) {}
 }
 
+/// Turns the messages sent by the layer into captures and ring buffer frames.
+struct Writer {
+    sink: Option<CaptureSink>,
+    encoder: Box<dyn TraceEncoder>,
+    write: Vec<u8>,
+    ring: Option<RingBuffer>,
+    filter: FilterState,
+    frame: usize,
+    /// Bumped whenever threads have to be named again.
+    session: Arc<AtomicU64>,
+}
+
+impl Writer {
+    fn new(session: Arc<AtomicU64>) -> Self {
+        Self {
+            sink: None,
+            encoder: Box::new(ChromeEncoder::default()),
+            write: Vec::with_capacity(CHUNK_SIZE),
+            ring: None,
+            filter: FilterState::default(),
+            frame: 0,
+            session,
+        }
+    }
+
+    fn handle(&mut self, msg: Message) {
+        match msg {
+            Message::Start(ts, new_sink, format, new_filter) => {
+                self.write.clear();
+                self.encoder = format.encoder();
+                self.filter = FilterState::new(new_filter);
+                self.frame = 0;
+                self.encoder.begin(&mut self.write);
+                begin_frame(self.encoder.as_mut(), &mut self.write, ts, self.frame);
+                self.session.fetch_add(1, Ordering::Relaxed);
+                self.sink = Some(new_sink);
+            }
+            Message::EndFrame(ts, last, counters, notify) => match self.sink.take() {
+                Some(mut current) => {
+                    let dropped = self.filter.take_dropped();
+                    if dropped > 0 {
+                        let truncated = Message::Event(ts, truncated_callsite(dropped));
+                        self.encoder.encode(&mut self.write, &truncated);
+                    }
+                    for (name, value) in counters {
+                        self.encoder
+                            .encode(&mut self.write, &Message::Counter(ts, name, value));
+                    }
+                    let frame_span = Message::Exit(ts, frame_callsite(self.frame));
+                    self.encoder.encode(&mut self.write, &frame_span);
+                    self.encoder.end_frame(&mut self.write, last);
+                    self.frame += 1;
+                    // Every frame names its threads again so frames can be viewed on their own.
+                    self.session.fetch_add(1, Ordering::Relaxed);
+                    if last {
+                        // The rest of the capture may have to wait for the client, which must
+                        // not hold up the writer.
+                        let chunk = std::mem::take(&mut self.write);
+                        std::thread::spawn(move || {
+                            current.write(chunk);
+                            current.finish();
+                            notify.notify_one();
+                        });
+                    } else {
+                        self.sink = Some(current);
+                        flush(&mut self.sink, &mut self.write);
+                        if self.sink.is_some() {
+                            begin_frame(self.encoder.as_mut(), &mut self.write, ts, self.frame);
+                        }
+                        notify.notify_one();
+                    }
+                }
+                None => notify.notify_one(),
+            },
+            Message::RingBuffer(ring_buffer) => self.ring = Some(ring_buffer),
+            Message::RingFrame => {
+                if let Some(ring) = self.ring.as_mut() {
+                    ring.end_frame();
+                    // Buffered frames need their thread names as well.
+                    self.session.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Message::Snapshot(reason) => {
+                if let Some(ring) = self.ring.as_mut() {
+                    ring.snapshot(reason);
+                }
+            }
+            msg => {
+                if self.sink.is_some() {
+                    if self.write.len() >= CHUNK_SIZE {
+                        flush(&mut self.sink, &mut self.write);
+                    }
+                    // While the client can't keep up, new spans and events are dropped instead
+                    // of piling up in memory. Spans that were written are still closed.
+                    let full = self.write.len() >= CHUNK_SIZE;
+                    let (encoder, write) = (&mut self.encoder, &mut self.write);
+                    self.filter
+                        .apply(&msg, full, |msg| encoder.encode(write, msg));
+                }
+                if let Some(ring) = self.ring.as_mut() {
+                    ring.push(msg);
+                }
+            }
+        }
+    }
+}
+
+/// Output format of a capture.
+#[derive(Serialize, Deserialize, Schema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TraceFormat {
+    /// Chrome trace event JSON, one array of events per frame.
+    #[default]
+    Chrome,
+    /// Perfetto `Trace` protobuf, for ui.perfetto.dev and trace_processor.
+    Perfetto,
+}
+
+impl TraceFormat {
+    pub(super) fn encoder(self) -> Box<dyn TraceEncoder> {
+        match self {
+            TraceFormat::Chrome => Box::new(ChromeEncoder::default()),
+            TraceFormat::Perfetto => Box::new(PerfettoEncoder::default()),
+        }
+    }
+
+    pub(super) fn content_type(self) -> &'static str {
+        match self {
+            TraceFormat::Chrome => "application/json",
+            TraceFormat::Perfetto => "application/octet-stream",
+        }
+    }
+
+    pub(super) fn extension(self) -> &'static str {
+        match self {
+            TraceFormat::Chrome => "json",
+            TraceFormat::Perfetto => "pftrace",
+        }
+    }
+}
+
+/// Hands the written trace to `sink` without blocking the writer. While a stream is full the
+/// trace stays in `write`.
+fn flush(sink: &mut Option<CaptureSink>, write: &mut Vec<u8>) {
+    if let Some(current) = sink.as_mut() {
+        match current.try_write(std::mem::take(write)) {
+            Ok(()) => *write = Vec::with_capacity(CHUNK_SIZE),
+            Err(Some(chunk)) => *write = chunk,
+            Err(None) => *sink = None,
+        }
+    }
+}
+
+/// Callsite of the synthetic span that wraps the `frame`th frame of a capture.
+fn frame_callsite(frame: usize) -> Callsite {
+    Callsite {
+        tid: FRAMES_TID,
+        name: format!("frame {}", frame),
+        target: "frame".to_string(),
+        file: None,
+        line: None,
+        args: None,
+    }
+}
+
+/// Callsite of the instant event that marks a frame in which `dropped` spans and events were
+/// left out because the client fell behind.
+fn truncated_callsite(dropped: usize) -> Callsite {
+    let mut args = Object::new();
+    args.insert("dropped", dropped.into());
+    Callsite {
+        tid: FRAMES_TID,
+        name: "truncated".to_string(),
+        target: "frame".to_string(),
+        file: None,
+        line: None,
+        args: Some(Arc::new(args)),
+    }
+}
+
+fn begin_frame(encoder: &mut dyn TraceEncoder, out: &mut Vec<u8>, ts: f64, frame: usize) {
+    encoder.encode(out, &Message::NewThread(FRAMES_TID, "Frames".to_string()));
+    encoder.encode(out, &Message::Enter(ts, frame_callsite(frame)));
+}
+
+/// Turns the messages of a capture into the bytes of a trace file.
+pub(super) trait TraceEncoder: Send {
+    fn begin(&mut self, out: &mut Vec<u8>);
+    fn end_frame(&mut self, out: &mut Vec<u8>, last: bool);
+    fn encode(&mut self, out: &mut Vec<u8>, message: &Message);
+}
+
+/// Writes captures as a JSON array that contains one array of trace events per frame.
+struct ChromeEncoder {
+    first_in_frame: bool,
+}
+
+impl Default for ChromeEncoder {
+    fn default() -> Self {
+        ChromeEncoder {
+            first_in_frame: true,
+        }
+    }
+}
+
+impl TraceEncoder for ChromeEncoder {
+    fn begin(&mut self, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"[[");
+        self.first_in_frame = true;
+    }
+
+    fn end_frame(&mut self, out: &mut Vec<u8>, last: bool) {
+        out.extend_from_slice(if last { b"]]" } else { b"],\n[" });
+        self.first_in_frame = true;
+    }
+
+    fn encode(&mut self, out: &mut Vec<u8>, msg: &Message) {
+        let mut entry = Object::new();
+
+        if let Message::Counter(ts, name, value) = msg {
+            entry.insert("ph", "C".into());
+            entry.insert("pid", 1.into());
+            entry.insert("ts", JsonValue::Number(Number::from(*ts)));
+            entry.insert("name", (*name).into());
+            let mut args = Object::new();
+            args.insert(name, (*value).into());
+            entry.insert("args", args.into());
+            self.write_entry(out, entry);
+            return;
+        }
+
+        let (ph, ts, callsite, id) = match msg {
+            Message::Enter(ts, callsite) => ("B", Some(ts), Some(callsite), None),
+            Message::Event(ts, callsite) => ("i", Some(ts), Some(callsite), None),
+            Message::Exit(ts, callsite) => ("E", Some(ts), Some(callsite), None),
+            Message::FlowStart(ts, callsite, id) => ("s", Some(ts), Some(callsite), Some(id)),
+            Message::FlowEnd(ts, callsite, id) => ("f", Some(ts), Some(callsite), Some(id)),
+            Message::NewThread(_tid, _name) => ("M", None, None, None),
+            _ => unreachable!("Control messages are handled by the writer."),
+        };
+        entry.insert("ph", ph.to_string().into());
+        entry.insert("pid", 1.into());
+
+        if let Message::NewThread(tid, name) = msg {
+            entry.insert("name", "thread_name".to_string().into());
+            entry.insert("tid", (*tid).into());
+            let mut args = Object::new();
+            args.insert("name", name.clone().into());
+            entry.insert("args", args.into());
+        } else {
+            let ts = ts.unwrap();
+            let callsite = callsite.unwrap();
+            entry.insert("ts", JsonValue::Number(Number::from(*ts)));
+            entry.insert("name", callsite.name.clone().into());
+            entry.insert("cat", callsite.target.clone().into());
+            entry.insert("tid", callsite.tid.into());
+
+            if let Some(&id) = id {
+                entry.insert("id", id.into());
+            }
+
+            if ph == "i" {
+                entry.insert("s", "p".into());
+            }
+            if ph == "f" {
+                entry.insert("bp", "e".into());
+            }
+
+            let mut args = Object::new();
+            if let (Some(file), Some(line)) = (callsite.file, callsite.line) {
+                args.insert("[file]", file.to_string().into());
+                args.insert("[line]", line.into());
+            }
+
+            if let Some(call_args) = &callsite.args {
+                for (k, v) in call_args.iter() {
+                    args.insert(k, v.clone());
+                }
+            }
+
+            if !args.is_empty() {
+                entry.insert("args", args.into());
+            }
+        }
+
+        self.write_entry(out, entry);
+    }
+}
+
+impl ChromeEncoder {
+    fn write_entry(&mut self, out: &mut Vec<u8>, entry: Object) {
+        if !self.first_in_frame {
+            out.extend_from_slice(b",\n");
+        }
+        self.first_in_frame = false;
+        out.extend_from_slice(entry.dump().as_bytes());
+    }
+}
+
 struct JsonVisitor<'a> {
     object: &'a mut json::object::Object,
 }
@@ -373,6 +817,102 @@ impl<'a> tracing_subscriber::field::Visit for JsonVisitor<'a> {
     }
 }
 
+/// Formats recorded fields as `name=value` pairs.
+fn format_fields(fields: Option<&Object>) -> String {
+    let mut formatted = String::new();
+    for (name, value) in fields.into_iter().flat_map(|fields| fields.iter()) {
+        if !formatted.is_empty() {
+            formatted.push(' ');
+        }
+        match value.as_str() {
+            Some(value) => write!(formatted, "{}={}", name, value).unwrap(),
+            None => write!(formatted, "{}={}", name, value.dump()).unwrap(),
+        }
+    }
+    formatted
+}
+
+/// Thread and time a span was created at.
+struct SpanOrigin {
+    tid: u64,
+    ts: f64,
+}
+
+/// Flows from `follows_from` relations that end once the span is entered, with the callsite
+/// they started at. Both ends of a flow need the same name and category.
+struct PendingFlows(Vec<(u64, Callsite)>);
+
 struct ArgsWrapper {
     args: Arc<Object>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn callsite(name: &str, args: Option<Arc<Object>>) -> Callsite {
+        Callsite {
+            tid: 1,
+            name: name.to_string(),
+            target: "test".to_string(),
+            file: None,
+            line: None,
+            args,
+        }
+    }
+
+    #[test]
+    fn slow_sink_keeps_slices_balanced() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let mut writer = Writer::new(Arc::new(AtomicU64::new(0)));
+        writer.handle(Message::Start(
+            0.0,
+            CaptureSink::Stream(sender),
+            TraceFormat::Chrome,
+            CaptureFilter::default(),
+        ));
+
+        let mut args = Object::new();
+        args.insert("padding", "x".repeat(1024).into());
+        let args = Some(Arc::new(args));
+        // Nobody reads from the stream until the capture is done, so it is full after the
+        // first chunk and most spans and events are dropped.
+        for frame in 0..3 {
+            writer.handle(Message::Enter(0.0, callsite("outer", None)));
+            for _ in 0..3000 {
+                writer.handle(Message::Enter(0.0, callsite("inner", args.clone())));
+                writer.handle(Message::Event(0.0, callsite("event", args.clone())));
+                writer.handle(Message::Exit(0.0, callsite("inner", args.clone())));
+            }
+            writer.handle(Message::Exit(0.0, callsite("outer", None)));
+            let notify = Arc::new(Notify::new());
+            writer.handle(Message::EndFrame(0.0, frame == 2, Vec::new(), notify));
+        }
+
+        let mut trace = Vec::new();
+        while let Some(chunk) = receiver.blocking_recv() {
+            trace.extend(chunk);
+        }
+        let trace = json::parse(std::str::from_utf8(&trace).unwrap()).unwrap();
+
+        let mut depths = HashMap::<(u64, String), i64>::new();
+        let mut truncated = 0;
+        for event in trace.members().flat_map(|frame| frame.members()) {
+            let key = (
+                event["tid"].as_u64().unwrap(),
+                event["name"].as_str().unwrap_or_default().to_string(),
+            );
+            match event["ph"].as_str().unwrap() {
+                "B" => *depths.entry(key).or_default() += 1,
+                "E" => *depths.entry(key).or_default() -= 1,
+                "i" if event["name"] == "truncated" => truncated += 1,
+                _ => {}
+            }
+        }
+        assert!(truncated > 0);
+        assert!(depths.values().all(|depth| *depth == 0), "{:?}", depths);
+        assert!(depths.contains_key(&(1, "outer".to_string())));
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use super::chrome::{Callsite, Message};
+
+/// Filter of a single capture, independent of the log filter. Every list is comma separated.
+#[derive(Default, Debug)]
+pub(crate) struct CaptureFilter {
+    /// Target prefixes of recorded spans and events.
+    targets: Vec<String>,
+    /// Names of recorded spans.
+    spans: Vec<String>,
+    /// Name prefixes of recorded threads.
+    threads: Vec<String>,
+    /// Duration in microseconds below which spans are dropped.
+    min_duration: f64,
+}
+
+impl CaptureFilter {
+    pub(crate) fn new(
+        targets: Option<&str>,
+        spans: Option<&str>,
+        threads: Option<&str>,
+        min_duration_ms: Option<f64>,
+    ) -> Self {
+        Self {
+            targets: split_list(targets),
+            spans: split_list(spans),
+            threads: split_list(threads),
+            min_duration: min_duration_ms.unwrap_or(0.0) * 1000.0,
+        }
+    }
+
+    fn matches_thread(&self, name: Option<&String>) -> bool {
+        self.threads.is_empty()
+            || self.threads.iter().any(|prefix| match name {
+                Some(name) => name.starts_with(prefix.as_str()),
+                None => false,
+            })
+    }
+
+    fn matches_target(&self, callsite: &Callsite) -> bool {
+        self.targets.is_empty()
+            || self
+                .targets
+                .iter()
+                .any(|prefix| callsite.target.starts_with(prefix.as_str()))
+    }
+
+    fn matches_span(&self, callsite: &Callsite) -> bool {
+        self.matches_target(callsite)
+            && (self.spans.is_empty() || self.spans.contains(&callsite.name))
+    }
+}
+
+fn split_list(list: Option<&str>) -> Vec<String> {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+enum Entered {
+    Dropped,
+    Written,
+    /// Entered span that is only written once it turns out to be long enough.
+    Deferred(f64, Callsite),
+}
+
+/// Applies a `CaptureFilter` to the messages of a running capture.
+#[derive(Default)]
+pub(super) struct FilterState {
+    filter: CaptureFilter,
+    thread_names: HashMap<u64, String>,
+    /// Spans entered on each thread, spans are entered and exited in stack order.
+    entered: HashMap<u64, Vec<Entered>>,
+    /// Spans and events dropped because the sink was full, since the last `take_dropped`.
+    dropped: usize,
+}
+
+impl FilterState {
+    pub(super) fn new(filter: CaptureFilter) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    /// Passes the messages that should be written for `message` to `write`. Spans shorter
+    /// than the minimum duration are held back until they are exited. While `full`, only exits
+    /// of written spans are passed on so every written span is closed.
+    pub(super) fn apply(&mut self, message: &Message, full: bool, mut write: impl FnMut(&Message)) {
+        match message {
+            Message::NewThread(tid, name) => {
+                self.thread_names.insert(*tid, name.clone());
+                if self.filter.matches_thread(Some(name)) {
+                    write(message);
+                }
+            }
+            Message::Event(_, callsite)
+            | Message::FlowStart(_, callsite, _)
+            | Message::FlowEnd(_, callsite, _) => {
+                if self.matches_thread(callsite) && self.filter.matches_target(callsite) {
+                    if full {
+                        self.dropped += 1;
+                    } else {
+                        write(message);
+                    }
+                }
+            }
+            Message::Enter(ts, callsite) => {
+                let entered =
+                    if !self.matches_thread(callsite) || !self.filter.matches_span(callsite) {
+                        Entered::Dropped
+                    } else if full {
+                        self.dropped += 1;
+                        Entered::Dropped
+                    } else if self.filter.min_duration > 0.0 {
+                        Entered::Deferred(*ts, callsite.clone())
+                    } else {
+                        write(message);
+                        Entered::Written
+                    };
+                self.entered.entry(callsite.tid).or_default().push(entered);
+            }
+            Message::Exit(ts, callsite) => {
+                let entered = self
+                    .entered
+                    .get_mut(&callsite.tid)
+                    .and_then(|entered| entered.pop());
+                match entered {
+                    Some(Entered::Dropped) => {}
+                    Some(Entered::Written) => write(message),
+                    Some(Entered::Deferred(enter_ts, enter_callsite))
+                        if ts - enter_ts >= self.filter.min_duration =>
+                    {
+                        if full {
+                            self.dropped += 1;
+                        } else {
+                            write(&Message::Enter(enter_ts, enter_callsite));
+                            write(message);
+                        }
+                    }
+                    Some(Entered::Deferred(..)) => {}
+                    // Spans entered before the capture started.
+                    None => {
+                        if !full
+                            && self.matches_thread(callsite)
+                            && self.filter.matches_span(callsite)
+                            && self.filter.min_duration <= 0.0
+                        {
+                            write(message);
+                        }
+                    }
+                }
+            }
+            _ => write(message),
+        }
+    }
+
+    /// Amount of spans and events dropped since the last call.
+    pub(super) fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+
+    fn matches_thread(&self, callsite: &Callsite) -> bool {
+        self.filter
+            .matches_thread(self.thread_names.get(&callsite.tid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callsite(tid: u64, name: &str, target: &str) -> Callsite {
+        Callsite {
+            tid,
+            name: name.to_string(),
+            target: target.to_string(),
+            file: None,
+            line: None,
+            args: None,
+        }
+    }
+
+    /// Applies `messages` and returns what was written as phase and name.
+    fn written(state: &mut FilterState, messages: &[Message], full: bool) -> Vec<String> {
+        let mut written = Vec::new();
+        for message in messages {
+            state.apply(message, full, |message| {
+                written.push(match message {
+                    Message::Enter(_, callsite) => format!("B {}", callsite.name),
+                    Message::Exit(_, callsite) => format!("E {}", callsite.name),
+                    Message::Event(_, callsite) => format!("i {}", callsite.name),
+                    Message::NewThread(_, name) => format!("M {}", name),
+                    _ => String::new(),
+                })
+            });
+        }
+        written
+    }
+
+    #[test]
+    fn spans_are_filtered_by_target_and_name() {
+        let filter = CaptureFilter::new(Some("game, render"), Some("update,draw"), None, None);
+        let mut state = FilterState::new(filter);
+        let messages = [
+            Message::Enter(0.0, callsite(1, "update", "game::physics")),
+            Message::Enter(1.0, callsite(1, "update", "bevy_ecs")),
+            Message::Event(2.0, callsite(1, "event", "bevy_ecs")),
+            Message::Event(2.0, callsite(1, "event", "render")),
+            Message::Exit(3.0, callsite(1, "update", "bevy_ecs")),
+            Message::Enter(4.0, callsite(1, "other", "game")),
+            Message::Exit(5.0, callsite(1, "other", "game")),
+            Message::Exit(6.0, callsite(1, "update", "game::physics")),
+        ];
+        assert_eq!(
+            written(&mut state, &messages, false),
+            vec!["B update", "i event", "E update"]
+        );
+    }
+
+    #[test]
+    fn spans_are_filtered_by_thread() {
+        let filter = CaptureFilter::new(None, None, Some("Compute"), None);
+        let mut state = FilterState::new(filter);
+        let messages = [
+            Message::NewThread(1, "Main".to_string()),
+            Message::NewThread(2, "Compute Task Pool (0)".to_string()),
+            Message::Enter(0.0, callsite(1, "main", "game")),
+            Message::Enter(0.0, callsite(2, "task", "game")),
+            Message::Exit(1.0, callsite(2, "task", "game")),
+            Message::Exit(1.0, callsite(1, "main", "game")),
+        ];
+        assert_eq!(
+            written(&mut state, &messages, false),
+            vec!["M Compute Task Pool (0)", "B task", "E task"]
+        );
+    }
+
+    #[test]
+    fn short_spans_are_dropped() {
+        let filter = CaptureFilter::new(None, None, None, Some(1.0));
+        let mut state = FilterState::new(filter);
+        let messages = [
+            Message::Enter(0.0, callsite(1, "long", "game")),
+            Message::Enter(100.0, callsite(1, "short", "game")),
+            Message::Exit(200.0, callsite(1, "short", "game")),
+            Message::Exit(1000.0, callsite(1, "long", "game")),
+        ];
+        assert_eq!(
+            written(&mut state, &messages, false),
+            vec!["B long", "E long"]
+        );
+    }
+
+    #[test]
+    fn full_sinks_only_close_written_spans() {
+        let mut state = FilterState::new(CaptureFilter::default());
+        let enter = [Message::Enter(0.0, callsite(1, "outer", "game"))];
+        assert_eq!(written(&mut state, &enter, false), vec!["B outer"]);
+
+        let messages = [
+            Message::Enter(1.0, callsite(1, "inner", "game")),
+            Message::Event(2.0, callsite(1, "event", "game")),
+            Message::Exit(3.0, callsite(1, "inner", "game")),
+            Message::Exit(4.0, callsite(1, "outer", "game")),
+        ];
+        assert_eq!(written(&mut state, &messages, true), vec!["E outer"]);
+        assert_eq!(state.take_dropped(), 2);
+        assert_eq!(state.take_dropped(), 0);
+    }
+}
@@ -0,0 +1,329 @@
+use json::JsonValue;
+
+use super::chrome::{Callsite, Message, TraceEncoder};
+
+// Field numbers of perfetto's trace protos, see `protos/perfetto/trace` in the perfetto repo.
+const TRACE_PACKET: u32 = 1;
+
+const PACKET_TIMESTAMP: u32 = 8;
+const PACKET_SEQUENCE_ID: u32 = 10;
+const PACKET_TRACK_EVENT: u32 = 11;
+const PACKET_SEQUENCE_FLAGS: u32 = 13;
+const PACKET_TRACK_DESCRIPTOR: u32 = 60;
+
+const TRACK_UUID: u32 = 1;
+const TRACK_NAME: u32 = 2;
+const TRACK_PROCESS: u32 = 3;
+const TRACK_THREAD: u32 = 4;
+const TRACK_PARENT_UUID: u32 = 5;
+const TRACK_COUNTER: u32 = 8;
+
+const PROCESS_PID: u32 = 1;
+const PROCESS_NAME: u32 = 6;
+
+const THREAD_PID: u32 = 1;
+const THREAD_TID: u32 = 2;
+const THREAD_NAME: u32 = 5;
+
+const EVENT_DEBUG_ANNOTATIONS: u32 = 4;
+const EVENT_TYPE: u32 = 9;
+const EVENT_TRACK_UUID: u32 = 11;
+const EVENT_CATEGORIES: u32 = 22;
+const EVENT_NAME: u32 = 23;
+const EVENT_DOUBLE_COUNTER_VALUE: u32 = 44;
+
+const ANNOTATION_STRING_VALUE: u32 = 6;
+const ANNOTATION_NAME: u32 = 10;
+
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const TYPE_INSTANT: u64 = 3;
+const TYPE_COUNTER: u64 = 4;
+
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+
+const SEQUENCE_ID: u64 = 1;
+const PID: u64 = 1;
+const PROCESS_TRACK: u64 = 1;
+/// Counters get tracks from here on, far away from the thread tracks.
+const COUNTER_TRACKS: u64 = 1 << 32;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LENGTH_DELIMITED: u64 = 2;
+
+/// Writes captures as perfetto `Trace` protobuf, one `TracePacket` per message. Packets are
+/// self-contained so the output can be cut into chunks anywhere between them.
+#[derive(Default)]
+pub(super) struct PerfettoEncoder {
+    /// Counters whose track was already described, the index is part of the track uuid.
+    counters: Vec<&'static str>,
+}
+
+impl TraceEncoder for PerfettoEncoder {
+    fn begin(&mut self, out: &mut Vec<u8>) {
+        self.counters.clear();
+        write_packet(out, None, |packet| {
+            write_uint(packet, PACKET_SEQUENCE_FLAGS, SEQ_INCREMENTAL_STATE_CLEARED);
+            write_message(packet, PACKET_TRACK_DESCRIPTOR, |track| {
+                write_uint(track, TRACK_UUID, PROCESS_TRACK);
+                write_message(track, TRACK_PROCESS, |process| {
+                    write_uint(process, PROCESS_PID, PID);
+                    write_string(process, PROCESS_NAME, "bevy");
+                });
+            });
+        });
+    }
+
+    fn end_frame(&mut self, _out: &mut Vec<u8>, _last: bool) {}
+
+    fn encode(&mut self, out: &mut Vec<u8>, message: &Message) {
+        match message {
+            Message::NewThread(tid, name) => write_packet(out, None, |packet| {
+                write_message(packet, PACKET_TRACK_DESCRIPTOR, |track| {
+                    write_uint(track, TRACK_UUID, thread_track(*tid));
+                    write_uint(track, TRACK_PARENT_UUID, PROCESS_TRACK);
+                    write_string(track, TRACK_NAME, name);
+                    write_message(track, TRACK_THREAD, |thread| {
+                        write_uint(thread, THREAD_PID, PID);
+                        write_uint(thread, THREAD_TID, *tid);
+                        write_string(thread, THREAD_NAME, name);
+                    });
+                });
+            }),
+            Message::Enter(ts, callsite) => {
+                write_track_event(out, *ts, TYPE_SLICE_BEGIN, callsite, true)
+            }
+            Message::Exit(ts, callsite) => {
+                write_track_event(out, *ts, TYPE_SLICE_END, callsite, false)
+            }
+            Message::Event(ts, callsite) => {
+                write_track_event(out, *ts, TYPE_INSTANT, callsite, true)
+            }
+            Message::Counter(ts, name, value) => {
+                let track = self.counter_track(out, name);
+                write_packet(out, Some((ts * 1000.0) as u64), |packet| {
+                    write_message(packet, PACKET_TRACK_EVENT, |event| {
+                        write_uint(event, EVENT_TYPE, TYPE_COUNTER);
+                        write_uint(event, EVENT_TRACK_UUID, track);
+                        write_double(event, EVENT_DOUBLE_COUNTER_VALUE, *value);
+                    });
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl PerfettoEncoder {
+    /// Track of the counter `name`, described when it is first used.
+    fn counter_track(&mut self, out: &mut Vec<u8>, name: &'static str) -> u64 {
+        if let Some(index) = self.counters.iter().position(|counter| *counter == name) {
+            return COUNTER_TRACKS + index as u64;
+        }
+        let track = COUNTER_TRACKS + self.counters.len() as u64;
+        self.counters.push(name);
+        write_packet(out, None, |packet| {
+            write_message(packet, PACKET_TRACK_DESCRIPTOR, |descriptor| {
+                write_uint(descriptor, TRACK_UUID, track);
+                write_uint(descriptor, TRACK_PARENT_UUID, PROCESS_TRACK);
+                write_string(descriptor, TRACK_NAME, name);
+                write_message(descriptor, TRACK_COUNTER, |_| {});
+            });
+        });
+        track
+    }
+}
+
+/// Threads get their own tracks below the process track.
+fn thread_track(tid: u64) -> u64 {
+    tid + PROCESS_TRACK + 1
+}
+
+fn write_track_event(out: &mut Vec<u8>, ts: f64, ty: u64, callsite: &Callsite, details: bool) {
+    // Chrome timestamps are in microseconds, perfetto uses nanoseconds.
+    let timestamp = (ts * 1000.0) as u64;
+    write_packet(out, Some(timestamp), |packet| {
+        write_message(packet, PACKET_TRACK_EVENT, |event| {
+            write_uint(event, EVENT_TYPE, ty);
+            write_uint(event, EVENT_TRACK_UUID, thread_track(callsite.tid));
+            // Slice ends are matched with their begin by perfetto.
+            if !details {
+                return;
+            }
+            write_string(event, EVENT_NAME, &callsite.name);
+            write_string(event, EVENT_CATEGORIES, &callsite.target);
+            if let (Some(file), Some(line)) = (callsite.file, callsite.line) {
+                write_annotation(event, "[file]", file);
+                write_annotation(event, "[line]", &line.to_string());
+            }
+            if let Some(args) = &callsite.args {
+                for (name, value) in args.iter() {
+                    match value {
+                        JsonValue::String(value) => write_annotation(event, name, value),
+                        JsonValue::Short(value) => write_annotation(event, name, value.as_str()),
+                        value => write_annotation(event, name, &value.dump()),
+                    }
+                }
+            }
+        });
+    });
+}
+
+fn write_annotation(out: &mut Vec<u8>, name: &str, value: &str) {
+    write_message(out, EVENT_DEBUG_ANNOTATIONS, |annotation| {
+        write_string(annotation, ANNOTATION_NAME, name);
+        write_string(annotation, ANNOTATION_STRING_VALUE, value);
+    });
+}
+
+fn write_packet(out: &mut Vec<u8>, timestamp: Option<u64>, body: impl FnOnce(&mut Vec<u8>)) {
+    write_message(out, TRACE_PACKET, |packet| {
+        if let Some(timestamp) = timestamp {
+            write_uint(packet, PACKET_TIMESTAMP, timestamp);
+        }
+        write_uint(packet, PACKET_SEQUENCE_ID, SEQUENCE_ID);
+        body(packet);
+    });
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_uint(out: &mut Vec<u8>, field: u32, value: u64) {
+    write_varint(out, (field as u64) << 3 | WIRE_VARINT);
+    write_varint(out, value);
+}
+
+fn write_double(out: &mut Vec<u8>, field: u32, value: f64) {
+    write_varint(out, (field as u64) << 3 | WIRE_FIXED64);
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, field: u32, value: &str) {
+    write_varint(out, (field as u64) << 3 | WIRE_LENGTH_DELIMITED);
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_message(out: &mut Vec<u8>, field: u32, body: impl FnOnce(&mut Vec<u8>)) {
+    let mut message = Vec::new();
+    body(&mut message);
+    write_varint(out, (field as u64) << 3 | WIRE_LENGTH_DELIMITED);
+    write_varint(out, message.len() as u64);
+    out.extend_from_slice(&message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        out
+    }
+
+    /// Splits `data` into its fields, as field number and raw value.
+    fn read_fields(mut data: &[u8]) -> Vec<(u64, Vec<u8>)> {
+        fn read_varint(data: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = data[0];
+                *data = &data[1..];
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+
+        let mut fields = Vec::new();
+        while !data.is_empty() {
+            let key = read_varint(&mut data);
+            let value = match key & 7 {
+                WIRE_VARINT => varint(read_varint(&mut data)),
+                WIRE_FIXED64 => {
+                    let (value, rest) = data.split_at(8);
+                    data = rest;
+                    value.to_vec()
+                }
+                WIRE_LENGTH_DELIMITED => {
+                    let len = read_varint(&mut data) as usize;
+                    let (value, rest) = data.split_at(len);
+                    data = rest;
+                    value.to_vec()
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push((key >> 3, value));
+        }
+        fields
+    }
+
+    #[test]
+    fn varints_use_seven_bits_per_byte() {
+        assert_eq!(varint(0), vec![0]);
+        assert_eq!(varint(127), vec![0x7f]);
+        assert_eq!(varint(128), vec![0x80, 0x01]);
+        assert_eq!(varint(300), vec![0xac, 0x02]);
+        let max = varint(u64::MAX);
+        assert_eq!(max.len(), 10);
+        assert_eq!(max[9], 0x01);
+    }
+
+    #[test]
+    fn packets_are_length_delimited_with_timestamp_and_sequence() {
+        let mut out = Vec::new();
+        write_packet(&mut out, Some(1000), |_| {});
+        assert_eq!(out, vec![0x0a, 0x05, 0x40, 0xe8, 0x07, 0x50, 0x01]);
+    }
+
+    #[test]
+    fn every_message_is_a_self_contained_packet() {
+        let callsite = Callsite {
+            tid: 3,
+            name: "span".to_string(),
+            target: "test".to_string(),
+            file: Some("src/lib.rs"),
+            line: Some(1),
+            args: None,
+        };
+        let mut encoder = PerfettoEncoder::default();
+        let mut out = Vec::new();
+        encoder.begin(&mut out);
+        for message in [
+            Message::NewThread(3, "main".to_string()),
+            Message::Enter(1.0, callsite.clone()),
+            Message::Counter(1.5, "fps", 60.0),
+            Message::Exit(2.0, callsite),
+        ] {
+            encoder.encode(&mut out, &message);
+        }
+
+        let packets = read_fields(&out);
+        // The counter track is described before its first value.
+        assert_eq!(packets.len(), 6);
+        for (field, packet) in &packets {
+            assert_eq!(*field, TRACE_PACKET as u64);
+            let fields = read_fields(packet);
+            assert!(fields.contains(&(PACKET_SEQUENCE_ID as u64, varint(SEQUENCE_ID))));
+        }
+
+        let event = read_fields(&packets[2].1)
+            .into_iter()
+            .find(|(field, _)| *field == PACKET_TRACK_EVENT as u64)
+            .unwrap()
+            .1;
+        let event = read_fields(&event);
+        assert!(event.contains(&(EVENT_TYPE as u64, varint(TYPE_SLICE_BEGIN))));
+        assert!(event.contains(&(EVENT_TRACK_UUID as u64, varint(thread_track(3)))));
+        assert!(event.contains(&(EVENT_NAME as u64, b"span".to_vec())));
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::utils::{
+    tracing::{
+        field::{Field, Visit},
+        span, Subscriber,
+    },
+    Instant,
+};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// Timings older than this are dropped from the summary.
+pub(crate) const PROFILER_WINDOW: Duration = Duration::from_secs(5);
+
+/// A finished span: the id of its key, when it exited and how long it was entered.
+type Sample = (usize, Instant, Duration);
+
+/// Samples of one thread that were not merged into `SPAN_TIMINGS` yet.
+type SampleBuffer = Arc<Mutex<Vec<Sample>>>;
+
+#[derive(Default)]
+struct SpanTimings {
+    /// Ids are handed out in `key_id` and never reused.
+    next_id: usize,
+    ids: HashMap<String, usize>,
+    keys: HashMap<usize, KeyTimings>,
+}
+
+struct KeyTimings {
+    key: String,
+    /// Samples within the profiler window.
+    samples: VecDeque<(Instant, Duration)>,
+    /// Last sample or creation of the key, keys unused for a whole window are evicted so span
+    /// names with changing values don't grow memory forever.
+    last_used: Instant,
+}
+
+lazy_static::lazy_static! {
+    static ref SPAN_TIMINGS: Mutex<SpanTimings> = Mutex::new(SpanTimings::default());
+    static ref SAMPLE_BUFFERS: Mutex<Vec<SampleBuffer>> = Mutex::new(Vec::new());
+}
+
+/// Bumped whenever keys are evicted, so threads drop their cached ids.
+static EVICTIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Key ids this thread already looked up, so `SPAN_TIMINGS` is only locked for new keys.
+    /// Valid as long as `EVICTIONS` matches the count stored next to them.
+    static KEY_IDS: RefCell<(usize, HashMap<String, usize>)> = RefCell::new((0, HashMap::new()));
+    /// Reused to build span keys without allocating for every span.
+    static KEY: RefCell<(String, String)> = RefCell::new((String::new(), String::new()));
+    /// Only locked by this thread and while merging, so it is practically uncontended.
+    static SAMPLES: SampleBuffer = {
+        let buffer = SampleBuffer::default();
+        SAMPLE_BUFFERS.lock().unwrap().push(buffer.clone());
+        buffer
+    };
+}
+
+#[derive(Deserialize, Schema, Debug, Default)]
+#[serde(default)]
+pub(crate) struct ProfilerQuery {
+    /// Only the `limit` spans with the highest mean duration.
+    limit: Option<usize>,
+    /// Only spans whose key starts with this, e.g. `system` or `node`.
+    prefix: Option<String>,
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct ProfilerSummary {
+    window_seconds: f64,
+    spans: Vec<SpanSummary>,
+}
+
+/// Durations of a span over the profiler window, in microseconds.
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct SpanSummary {
+    /// Span name followed by its `name` field if it has one, e.g. `system: my_game::physics`.
+    key: String,
+    count: usize,
+    min: f64,
+    mean: f64,
+    p95: f64,
+    max: f64,
+}
+
+/// Continuously aggregates how long spans take, independent of trace captures.
+pub struct ProfilerLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    _inner: PhantomData<S>,
+}
+
+impl<S> ProfilerLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    pub fn new() -> Self {
+        Self {
+            _inner: PhantomData,
+        }
+    }
+}
+
+struct SpanKey(usize);
+
+struct EnteredAt(Instant);
+
+/// Records the `name` field into a reused buffer.
+struct KeyVisitor<'a>(&'a mut String);
+
+impl Visit for KeyVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0.push_str(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            let _ = write!(self.0, "{:?}", value);
+        }
+    }
+}
+
+impl<S> Layer<S> for ProfilerLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        // Thread locals are gone while a thread shuts down, its spans are not profiled then.
+        let key_id = KEY.try_with(|key| {
+            let (key, name) = &mut *key.borrow_mut();
+            key.clear();
+            name.clear();
+            attrs.record(&mut KeyVisitor(name));
+            key.push_str(attrs.metadata().name());
+            if !name.is_empty() {
+                key.push_str(": ");
+                key.push_str(name.trim_matches('"'));
+            }
+            key_id(key)
+        });
+        if let (Ok(Some(key_id)), Some(span)) = (key_id, ctx.span(id)) {
+            span.extensions_mut().insert(SpanKey(key_id));
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().replace(EnteredAt(Instant::now()));
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let now = Instant::now();
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        let entered_at = match extensions.remove::<EnteredAt>() {
+            Some(entered_at) => entered_at.0,
+            None => return,
+        };
+        let key_id = match extensions.get_mut::<SpanKey>() {
+            Some(key) => key.0,
+            None => return,
+        };
+
+        let _ = SAMPLES.try_with(|samples| {
+            samples
+                .lock()
+                .unwrap()
+                .push((key_id, now, now - entered_at))
+        });
+    }
+}
+
+fn key_id(key: &str) -> Option<usize> {
+    KEY_IDS
+        .try_with(|ids| {
+            let (evictions, ids) = &mut *ids.borrow_mut();
+            let current_evictions = EVICTIONS.load(Ordering::Acquire);
+            if *evictions != current_evictions {
+                *evictions = current_evictions;
+                ids.clear();
+            }
+            if let Some(id) = ids.get(key) {
+                return *id;
+            }
+            let mut timings = SPAN_TIMINGS.lock().unwrap();
+            let id = match timings.ids.get(key) {
+                Some(id) => *id,
+                None => {
+                    let id = timings.next_id;
+                    timings.next_id += 1;
+                    timings.ids.insert(key.to_string(), id);
+                    timings.keys.insert(
+                        id,
+                        KeyTimings {
+                            key: key.to_string(),
+                            samples: VecDeque::new(),
+                            last_used: Instant::now(),
+                        },
+                    );
+                    id
+                }
+            };
+            ids.insert(key.to_string(), id);
+            id
+        })
+        .ok()
+}
+
+/// Moves the samples of all threads into `SPAN_TIMINGS`, runs once per frame.
+pub(crate) fn merge_samples() {
+    let now = Instant::now();
+    let mut timings = SPAN_TIMINGS.lock().unwrap();
+    let mut buffers = SAMPLE_BUFFERS.lock().unwrap();
+    for buffer in buffers.iter() {
+        for (key_id, time, duration) in buffer.lock().unwrap().drain(..) {
+            // Spans created before their key was evicted still carry its id.
+            if let Some(key) = timings.keys.get_mut(&key_id) {
+                key.samples.push_back((time, duration));
+                key.last_used = time;
+            }
+        }
+    }
+    // Buffers of threads that exited are only referenced here anymore.
+    buffers.retain(|buffer| Arc::strong_count(buffer) > 1);
+
+    let before = timings.keys.len();
+    timings.keys.retain(|_, key| {
+        drop_expired(&mut key.samples, now);
+        !key.samples.is_empty() || now.duration_since(key.last_used) <= PROFILER_WINDOW
+    });
+    if timings.keys.len() < before {
+        let SpanTimings { ids, keys, .. } = &mut *timings;
+        ids.retain(|_, id| keys.contains_key(id));
+        EVICTIONS.fetch_add(1, Ordering::Release);
+    }
+}
+
+fn drop_expired(samples: &mut VecDeque<(Instant, Duration)>, now: Instant) {
+    while let Some((time, _)) = samples.front() {
+        if now.duration_since(*time) <= PROFILER_WINDOW {
+            break;
+        }
+        samples.pop_front();
+    }
+}
+
+pub(crate) fn profiler_summary(query: &ProfilerQuery) -> ProfilerSummary {
+    merge_samples();
+    let timings = SPAN_TIMINGS.lock().unwrap();
+
+    let mut spans = timings
+        .keys
+        .values()
+        .filter(|key| !key.samples.is_empty())
+        .filter(|key| match &query.prefix {
+            Some(prefix) => key.key.starts_with(prefix.as_str()),
+            None => true,
+        })
+        .map(|key| {
+            let mut durations = key
+                .samples
+                .iter()
+                .map(|(_, duration)| duration.as_secs_f64() * 1_000_000.0)
+                .collect::<Vec<_>>();
+            durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let count = durations.len();
+            let p95_index = ((count as f64 * 0.95).ceil() as usize).max(1) - 1;
+            SpanSummary {
+                key: key.key.clone(),
+                count,
+                min: durations[0],
+                mean: durations.iter().sum::<f64>() / count as f64,
+                p95: durations[p95_index],
+                max: durations[count - 1],
+            }
+        })
+        .collect::<Vec<_>>();
+    spans.sort_by(|a, b| b.mean.partial_cmp(&a.mean).unwrap());
+    if let Some(limit) = query.limit {
+        spans.truncate(limit);
+    }
+
+    ProfilerSummary {
+        window_seconds: PROFILER_WINDOW.as_secs_f64(),
+        spans,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_keys_are_evicted() {
+        let key = "test: idle_keys_are_evicted";
+        let id = key_id(key).unwrap();
+        let expired = Instant::now() - PROFILER_WINDOW * 2;
+        SAMPLES.with(|samples| {
+            samples
+                .lock()
+                .unwrap()
+                .push((id, expired, Duration::from_millis(1)))
+        });
+        merge_samples();
+
+        assert!(!SPAN_TIMINGS.lock().unwrap().keys.contains_key(&id));
+        assert!(!SPAN_TIMINGS.lock().unwrap().ids.contains_key(key));
+        assert_ne!(key_id(key).unwrap(), id);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bevy::utils::tracing::{field::Field, span, Subscriber};
+use tracing_subscriber::{field::Visit, layer::Context, registry::LookupSpan, Layer};
+
+/// Set by the plugin when spawned entities should record the system that spawned them.
+pub(crate) static RECORD_SYSTEM_NAMES: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RUNNING_SYSTEMS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Name of the system currently running on this thread, taken from the `system` spans of
+/// bevys executors.
+pub(crate) fn current_system_name() -> Option<String> {
+    RUNNING_SYSTEMS.with(|systems| systems.borrow().last().cloned())
+}
+
+/// Keeps track of the systems running on each thread while `RECORD_SYSTEM_NAMES` is set.
+pub struct SystemNameLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    _inner: PhantomData<S>,
+}
+
+impl<S> SystemNameLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    pub fn new() -> Self {
+        Self {
+            _inner: PhantomData,
+        }
+    }
+}
+
+struct SystemName(String);
+
+pub(super) struct NameVisitor(pub(super) Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S> Layer<S> for SystemNameLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !RECORD_SYSTEM_NAMES.load(Ordering::Relaxed) || attrs.metadata().name() != "system" {
+            return;
+        }
+        let mut visitor = NameVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(name), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SystemName(name));
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(name) = span.extensions().get::<SystemName>() {
+                RUNNING_SYSTEMS.with(|systems| systems.borrow_mut().push(name.0.clone()));
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if span.extensions().get::<SystemName>().is_some() {
+                RUNNING_SYSTEMS.with(|systems| systems.borrow_mut().pop());
+            }
+        }
+    }
+}
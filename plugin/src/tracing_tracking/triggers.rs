@@ -0,0 +1,380 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    log::Level,
+    prelude::*,
+    utils::tracing::Metadata,
+};
+use chrono::Utc;
+use json::JsonValue;
+
+use super::{
+    captures::{self, CaptureMetadata},
+    chrome::{Callsite, CaptureSink, ChromeLayerController, Message, TraceFormat},
+};
+
+lazy_static::lazy_static! {
+    static ref EVENT_TARGETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+static TRIGGERS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the tracing data of the last `frames` frames and saves it as capture once a trigger
+/// fires, so hitches can be inspected after they happened. Snapshots are saved to the capture
+/// directory of the plugin.
+#[derive(Clone, Debug)]
+pub struct TraceTriggers {
+    pub frames: usize,
+    /// Approximate memory the buffered frames may take, older frames are dropped beyond it.
+    pub max_bytes: usize,
+    /// Frame time in milliseconds above which a snapshot is taken. Needs the
+    /// `FrameTimeDiagnosticsPlugin`.
+    pub frame_time_threshold: Option<f64>,
+    /// Targets whose `warn!` and `error!` events take a snapshot, matched by prefix.
+    pub event_targets: Vec<String>,
+}
+
+impl TraceTriggers {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: frames.max(1),
+            max_bytes: 64 * 1024 * 1024,
+            frame_time_threshold: None,
+            event_targets: Vec::new(),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_frame_time_threshold(mut self, milliseconds: f64) -> Self {
+        self.frame_time_threshold = Some(milliseconds);
+        self
+    }
+
+    pub fn with_event_target(mut self, target: impl Into<String>) -> Self {
+        self.event_targets.push(target.into());
+        self
+    }
+}
+
+/// Handle to the devtools from within the app.
+pub struct DevTools;
+
+impl DevTools {
+    /// Saves the buffered frames of `TraceTriggers` once the current frame ended. Does nothing
+    /// if no triggers are configured.
+    pub fn snapshot_trace(reason: impl Into<String>) {
+        if TRIGGERS_ENABLED.load(Ordering::Relaxed) {
+            ChromeLayerController::snapshot(reason.into());
+        }
+    }
+}
+
+/// Starts buffering frames for `triggers`, snapshots are saved to `dir`.
+pub(crate) fn enable(
+    app: &mut App,
+    triggers: TraceTriggers,
+    dir: PathBuf,
+    app_name: String,
+    app_version: String,
+) {
+    *EVENT_TARGETS.lock().unwrap() = triggers.event_targets.clone();
+    ChromeLayerController::ring_buffer(RingBuffer::new(
+        triggers.frames,
+        triggers.max_bytes,
+        dir,
+        app_name,
+        app_version,
+    ));
+    TRIGGERS_ENABLED.store(true, Ordering::Relaxed);
+
+    app.insert_resource(triggers);
+    app.add_system_to_stage("devtools_end", check_trace_triggers);
+}
+
+/// Reason for a snapshot if `metadata` belongs to a warning or error of a trigger target.
+pub(super) fn event_trigger(metadata: &Metadata) -> Option<String> {
+    if !TRIGGERS_ENABLED.load(Ordering::Relaxed) || *metadata.level() > Level::WARN {
+        return None;
+    }
+    let targets = EVENT_TARGETS.lock().unwrap();
+    if targets
+        .iter()
+        .any(|target| metadata.target().starts_with(target.as_str()))
+    {
+        Some(format!(
+            "{} event from {}",
+            metadata.level(),
+            metadata.target()
+        ))
+    } else {
+        None
+    }
+}
+
+fn check_trace_triggers(triggers: Res<TraceTriggers>, diagnostics: Option<Res<Diagnostics>>) {
+    let frame_time = diagnostics
+        .as_ref()
+        .and_then(|diagnostics| diagnostics.get(FrameTimeDiagnosticsPlugin::FRAME_TIME))
+        .and_then(|frame_time| frame_time.value())
+        .map(|seconds| seconds * 1000.0);
+    if let (Some(threshold), Some(frame_time)) = (triggers.frame_time_threshold, frame_time) {
+        if frame_time > threshold {
+            DevTools::snapshot_trace(format!("frame time of {:.2}ms", frame_time));
+        }
+    }
+    ChromeLayerController::ring_frame();
+}
+
+/// Tracing messages of the last frames, owned by the writer thread of the `ChromeLayer`.
+pub(super) struct RingBuffer {
+    capacity: usize,
+    max_bytes: usize,
+    /// The last frame is the one currently running.
+    frames: VecDeque<Vec<Message>>,
+    /// Approximate size of each frame, see `message_size`.
+    frame_bytes: VecDeque<usize>,
+    bytes: usize,
+    pending: Option<String>,
+    /// Frames until the next snapshot can be taken, so a lasting hitch doesn't save one
+    /// snapshot per frame.
+    cooldown: usize,
+    dir: PathBuf,
+    app: String,
+    version: String,
+}
+
+impl RingBuffer {
+    fn new(capacity: usize, max_bytes: usize, dir: PathBuf, app: String, version: String) -> Self {
+        Self {
+            capacity,
+            max_bytes,
+            frames: VecDeque::from(vec![Vec::new()]),
+            frame_bytes: VecDeque::from(vec![0]),
+            bytes: 0,
+            pending: None,
+            cooldown: 0,
+            dir,
+            app,
+            version,
+        }
+    }
+
+    /// Buffers `message`, dropping the oldest frames once the buffer exceeds `max_bytes`. Should
+    /// the running frame alone exceed it, its further spans and events are dropped.
+    pub(super) fn push(&mut self, message: Message) {
+        let size = message_size(&message);
+        while self.bytes + size > self.max_bytes && self.frames.len() > 1 {
+            self.pop_frame();
+        }
+        let droppable = matches!(
+            message,
+            Message::Enter(..) | Message::Event(..) | Message::FlowStart(..) | Message::FlowEnd(..)
+        );
+        if droppable && self.bytes + size > self.max_bytes {
+            return;
+        }
+        self.bytes += size;
+        *self.frame_bytes.back_mut().unwrap() += size;
+        self.frames.back_mut().unwrap().push(message);
+    }
+
+    fn pop_frame(&mut self) {
+        self.frames.pop_front();
+        self.bytes -= self.frame_bytes.pop_front().unwrap_or(0);
+    }
+
+    pub(super) fn snapshot(&mut self, reason: String) {
+        if self.cooldown == 0 && self.pending.is_none() {
+            self.pending = Some(reason);
+        }
+    }
+
+    /// Finishes the current frame and saves a pending snapshot.
+    pub(super) fn end_frame(&mut self) {
+        self.cooldown = self.cooldown.saturating_sub(1);
+        if let Some(reason) = self.pending.take() {
+            let frames = std::mem::take(&mut self.frames);
+            self.frame_bytes.clear();
+            self.bytes = 0;
+            let dir = self.dir.clone();
+            let (app, version) = (self.app.clone(), self.version.clone());
+            std::thread::spawn(move || save_snapshot(frames, &dir, app, version, reason));
+            self.cooldown = self.capacity;
+        }
+        if self.frames.len() >= self.capacity {
+            self.pop_frame();
+        }
+        self.frames.push_back(Vec::new());
+        self.frame_bytes.push_back(0);
+    }
+}
+
+/// Rough memory taken by a buffered message, its strings and args included.
+fn message_size(message: &Message) -> usize {
+    let callsite_size = |callsite: &Callsite| {
+        let args = callsite.args.as_ref().map_or(0, |args| {
+            args.iter()
+                .map(|(key, value)| key.len() + value.as_str().map_or(0, str::len))
+                .sum::<usize>()
+                + args.len() * std::mem::size_of::<JsonValue>()
+        });
+        callsite.name.len() + callsite.target.len() + args
+    };
+    std::mem::size_of::<Message>()
+        + match message {
+            Message::Enter(_, callsite)
+            | Message::Event(_, callsite)
+            | Message::Exit(_, callsite)
+            | Message::FlowStart(_, callsite, _)
+            | Message::FlowEnd(_, callsite, _) => callsite_size(callsite),
+            Message::NewThread(_, name) => name.len(),
+            _ => 0,
+        }
+}
+
+fn save_snapshot(
+    frames: VecDeque<Vec<Message>>,
+    dir: &std::path::Path,
+    app: String,
+    version: String,
+    reason: String,
+) {
+    let format = TraceFormat::Chrome;
+    let id = captures::new_capture_id();
+    let path = captures::capture_path(dir, &id, format);
+    let file = match File::create(&path) {
+        Ok(file) => file,
+        Err(_) => {
+            warn!("Could not save trace snapshot to {}", path.display());
+            return;
+        }
+    };
+
+    let timestamps = frames.iter().flatten().filter_map(|message| match message {
+        Message::Enter(ts, _) | Message::Event(ts, _) | Message::Exit(ts, _) => Some(*ts),
+        _ => None,
+    });
+    let (first, last) = timestamps.fold((f64::MAX, f64::MIN), |(first, last), ts| {
+        (first.min(ts), last.max(ts))
+    });
+
+    let mut sink = CaptureSink::File(BufWriter::new(file));
+    let out = encode_frames(&frames, format);
+    sink.write(out);
+    sink.finish();
+
+    let metadata = CaptureMetadata {
+        id,
+        app,
+        version,
+        frames: frames.len(),
+        format,
+        timestamp: Utc::now(),
+        duration: if last > first {
+            (last - first) / 1000.0
+        } else {
+            0.0
+        },
+        bytes: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+        reason: Some(reason),
+    };
+    let _ = captures::write_metadata(dir, &metadata);
+}
+
+fn encode_frames(frames: &VecDeque<Vec<Message>>, format: TraceFormat) -> Vec<u8> {
+    let mut encoder = format.encoder();
+    let mut out = Vec::new();
+    // Spans entered per thread. Spans entered before the oldest buffered frame, or whose enter
+    // was dropped, would only be exited and break the nesting of the slices.
+    let mut depths = HashMap::<u64, usize>::new();
+    encoder.begin(&mut out);
+    for (i, frame) in frames.iter().enumerate() {
+        for message in frame {
+            match message {
+                Message::Enter(_, callsite) => *depths.entry(callsite.tid).or_default() += 1,
+                Message::Exit(_, callsite) => match depths.get_mut(&callsite.tid) {
+                    Some(depth) if *depth > 0 => *depth -= 1,
+                    _ => continue,
+                },
+                _ => {}
+            }
+            encoder.encode(&mut out, message);
+        }
+        encoder.end_frame(&mut out, i == frames.len() - 1);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callsite(name: &str) -> Callsite {
+        Callsite {
+            tid: 1,
+            name: name.to_string(),
+            target: "test".to_string(),
+            file: None,
+            line: None,
+            args: None,
+        }
+    }
+
+    fn ring_buffer(max_bytes: usize) -> RingBuffer {
+        RingBuffer::new(10, max_bytes, PathBuf::new(), String::new(), String::new())
+    }
+
+    #[test]
+    fn ring_buffer_drops_frames_beyond_max_bytes() {
+        let frame_size = 2 * message_size(&Message::Enter(0.0, callsite("span")));
+        let mut ring = ring_buffer(3 * frame_size);
+        for _ in 0..5 {
+            ring.push(Message::Enter(0.0, callsite("span")));
+            ring.push(Message::Exit(0.0, callsite("span")));
+            ring.end_frame();
+        }
+        // Three complete frames and the running one.
+        assert_eq!(ring.frames.len(), 4);
+        assert_eq!(ring.bytes, 3 * frame_size);
+
+        // A single frame over the limit keeps exits only.
+        let mut ring = ring_buffer(message_size(&Message::Enter(0.0, callsite("outer"))));
+        ring.push(Message::Enter(0.0, callsite("outer")));
+        ring.push(Message::Enter(0.0, callsite("inner")));
+        ring.push(Message::Exit(0.0, callsite("inner")));
+        ring.push(Message::Exit(0.0, callsite("outer")));
+        assert_eq!(ring.frames[0].len(), 3);
+    }
+
+    #[test]
+    fn snapshots_skip_exits_without_enter() {
+        let frames = VecDeque::from(vec![vec![
+            Message::Exit(0.0, callsite("before")),
+            Message::Enter(1.0, callsite("span")),
+            Message::Exit(2.0, callsite("span")),
+        ]]);
+        let trace = encode_frames(&frames, TraceFormat::Chrome);
+        let trace = json::parse(std::str::from_utf8(&trace).unwrap()).unwrap();
+        let phases = trace
+            .members()
+            .flat_map(|frame| frame.members())
+            .filter(|event| event["name"] == "before" || event["name"] == "span")
+            .map(|event| event["ph"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(phases, vec!["B", "E"]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::convert::Infallible;
+
+use bevy::{
+    prelude::*,
+    reflect::{ReflectRef, TypeRegistration, TypeRegistryArc},
+};
+use rweb::*;
+use serde::Serialize;
+
+use crate::{
+    reflect::{ReflectDefault, ReflectResource},
+    sync::{execute_in_world, ExecutionChannel},
+};
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct TypeDetails {
+    name: String,
+    short_name: String,
+    /// `struct`, `tuple_struct`, `tuple`, `list`, `map` or `value`. Bevy doesn't store type
+    /// infos yet, so this is only known for types with a default or an instance in the world.
+    kind: Option<String>,
+    fields: Option<Vec<FieldDetails>>,
+    component: bool,
+    resource: bool,
+    default: bool,
+    deserialize: bool,
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct FieldDetails {
+    /// Field name, the index for tuple like types.
+    name: String,
+    #[serde(rename = "type")]
+    type_name: String,
+}
+
+/// Lists every type of the type registry with the shape of its fields and the reflect traits
+/// the tools can make use of.
+#[get("/v1/types")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn types() -> Result<Json<Vec<TypeDetails>>, Infallible> {
+    let types = execute_in_world(ExecutionChannel::FrameEnd, |world| {
+        let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+        let type_registry = type_registry.read();
+
+        let mut types = type_registry
+            .iter()
+            .map(|registration| describe_type(world, registration))
+            .collect::<Vec<_>>();
+        types.sort_by(|a, b| a.name.cmp(&b.name));
+        types
+    })
+    .await;
+
+    Ok(types.into())
+}
+
+fn describe_type(world: &World, registration: &TypeRegistration) -> TypeDetails {
+    let (kind, fields) = match registration.data::<ReflectDefault>() {
+        Some(reflect_default) => describe_shape(&*reflect_default.default()),
+        None => match find_instance(world, registration) {
+            Some(instance) => describe_shape(instance),
+            None => (None, None),
+        },
+    };
+
+    TypeDetails {
+        name: registration.name().to_string(),
+        short_name: registration.short_name().to_string(),
+        kind,
+        fields,
+        component: registration.data::<ReflectComponent>().is_some(),
+        resource: registration.data::<ReflectResource>().is_some(),
+        default: registration.data::<ReflectDefault>().is_some(),
+        deserialize: registration.data::<ReflectDeserialize>().is_some(),
+    }
+}
+
+/// Looks for an existing value of the type as component of any entity or as resource.
+fn find_instance<'a>(world: &'a World, registration: &TypeRegistration) -> Option<&'a dyn Reflect> {
+    if let Some(reflect_resource) = registration.data::<ReflectResource>() {
+        if let Some(resource) = reflect_resource.reflect(world) {
+            return Some(resource);
+        }
+    }
+
+    let reflect_component = registration.data::<ReflectComponent>()?;
+    let component_id = world.components().get_id(registration.type_id())?;
+    let entity = world
+        .archetypes()
+        .iter()
+        .filter(|archetype| archetype.contains(component_id))
+        .find_map(|archetype| archetype.entities().first().copied())?;
+    reflect_component.reflect_component(world, entity)
+}
+
+fn describe_shape(value: &dyn Reflect) -> (Option<String>, Option<Vec<FieldDetails>>) {
+    let (kind, fields) = match value.reflect_ref() {
+        ReflectRef::Struct(value) => (
+            "struct",
+            (0..value.field_len())
+                .map(|index| FieldDetails {
+                    name: value.name_at(index).unwrap().to_string(),
+                    type_name: value.field_at(index).unwrap().type_name().to_string(),
+                })
+                .collect(),
+        ),
+        ReflectRef::TupleStruct(value) => (
+            "tuple_struct",
+            (0..value.field_len())
+                .map(|index| FieldDetails {
+                    name: index.to_string(),
+                    type_name: value.field(index).unwrap().type_name().to_string(),
+                })
+                .collect(),
+        ),
+        ReflectRef::Tuple(value) => (
+            "tuple",
+            (0..value.field_len())
+                .map(|index| FieldDetails {
+                    name: index.to_string(),
+                    type_name: value.field(index).unwrap().type_name().to_string(),
+                })
+                .collect(),
+        ),
+        ReflectRef::List(_) => ("list", Vec::new()),
+        ReflectRef::Map(_) => ("map", Vec::new()),
+        ReflectRef::Value(_) => ("value", Vec::new()),
+    };
+    (Some(kind.to_string()), Some(fields))
+}
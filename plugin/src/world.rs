@@ -0,0 +1,154 @@
+use bevy::{
+    prelude::*,
+    reflect::{serde::ReflectDeserializer, TypeRegistryInternal},
+};
+use rweb::reject::Reject;
+use serde::de::DeserializeSeed;
+
+use crate::{
+    reflect::can_apply,
+    serialization::{entity_from_string, entity_to_string},
+    DevInfo, DevToolsSettings, NamePrecedence,
+};
+
+mod components;
+mod hierarchy;
+mod inspect;
+mod lifecycle;
+mod query;
+mod stream;
+
+pub(crate) use components::*;
+pub(crate) use hierarchy::*;
+pub(crate) use inspect::*;
+pub(crate) use lifecycle::*;
+pub(crate) use query::*;
+pub(crate) use stream::*;
+
+pub(crate) fn resolve_entity(world: &World, id: &str) -> Result<Entity, WorldErrors> {
+    entity_from_string(world, id)
+        .filter(|entity| world.get_entity(*entity).is_some())
+        .ok_or(WorldErrors::EntityNotFound)
+}
+
+/// Label of an entity shown in the tools, its `Name` or `DevInfo` name depending on the
+/// configured precedence and its id if it has neither.
+pub(crate) fn entity_label(world: &World, entity: Entity) -> String {
+    let name = world.get::<Name>(entity).map(|name| name.as_str());
+    let dev_name = world.get::<DevInfo>(entity).map(|info| info.name.as_str());
+    let precedence = world
+        .get_resource::<DevToolsSettings>()
+        .map(|settings| settings.name_precedence)
+        .unwrap_or_default();
+
+    let label = match precedence {
+        NamePrecedence::Name => name.or(dev_name),
+        NamePrecedence::DevInfo => dev_name.or(name),
+    };
+    match label {
+        Some(label) => label.to_string(),
+        None => entity_to_string(entity),
+    }
+}
+
+/// Deserializes a value for a field of type `type_name`. Values in the reflect format
+/// (`{ "type": ..., "value" | "struct" | ...: ... }`) are deserialized as they are,
+/// anything else is treated as plain serde representation of the target type.
+pub(crate) fn deserialize_value(
+    type_registry: &TypeRegistryInternal,
+    type_name: &str,
+    value: serde_json::Value,
+) -> Result<Box<dyn Reflect>, WorldErrors> {
+    let is_reflect_format = value
+        .as_object()
+        .map(|object| object.contains_key("type"))
+        .unwrap_or(false);
+
+    if is_reflect_format {
+        ReflectDeserializer::new(type_registry)
+            .deserialize(value)
+            .map_err(|_| WorldErrors::InvalidValue)
+    } else {
+        type_registry
+            .get_with_name(type_name)
+            .and_then(|registration| registration.data::<ReflectDeserialize>())
+            .ok_or(WorldErrors::InvalidValue)?
+            .deserialize(value)
+            .map_err(|_| WorldErrors::InvalidValue)
+    }
+}
+
+/// Applies `value` onto `target` without risking the panics `Reflect::apply` raises on
+/// mismatching types, including those of nested fields.
+pub(crate) fn apply_value(
+    target: &mut dyn Reflect,
+    value: Box<dyn Reflect>,
+) -> Result<(), WorldErrors> {
+    if value.type_name() != target.type_name() || !can_apply(target, &*value) {
+        return Err(WorldErrors::InvalidValue);
+    }
+    if let Err(value) = target.set(value) {
+        // Dynamic representations (structs, lists, ...) can only be applied.
+        target.apply(&*value);
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub(crate) enum WorldErrors {
+    EntityNotFound,
+    UnknownType,
+    NotAComponent,
+    ComponentNotFound,
+    InvalidPath,
+    InvalidValue,
+    InvalidHierarchy,
+    NoDefault,
+    NotAResource,
+    ResourceNotFound,
+}
+impl Reject for WorldErrors {}
+
+#[cfg(test)]
+mod tests {
+    use bevy::reflect::{DynamicList, DynamicStruct};
+
+    use super::*;
+
+    fn translation_patch(translation: impl Reflect) -> Box<dyn Reflect> {
+        let mut patch = DynamicStruct::default();
+        patch.set_name(std::any::type_name::<Transform>().to_string());
+        patch.insert("translation", translation);
+        Box::new(patch)
+    }
+
+    #[test]
+    fn nested_mismatch_is_rejected() {
+        let mut transform = Transform::default();
+        let mut translation = DynamicStruct::default();
+        translation.insert("x", String::from("a"));
+        let result = apply_value(&mut transform, translation_patch(translation));
+        assert!(matches!(result, Err(WorldErrors::InvalidValue)));
+        assert_eq!(transform, Transform::default());
+    }
+
+    #[test]
+    fn nested_patch_is_applied() {
+        let mut transform = Transform::default();
+        apply_value(&mut transform, translation_patch(Vec3::X * 2.0)).unwrap();
+        assert_eq!(transform.translation, Vec3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mismatching_list_items_are_rejected() {
+        let mut list = vec![1u32];
+        assert!(apply_value(&mut list, Box::new(vec![2u32, 3])).is_ok());
+        assert_eq!(list, vec![2, 3]);
+        let mut patch = DynamicList::default();
+        patch.set_name(std::any::type_name::<Vec<u32>>().to_string());
+        patch.push(2u32);
+        patch.push(String::from("a"));
+        assert!(apply_value(&mut list, Box::new(patch)).is_err());
+        assert_eq!(list, vec![2, 3]);
+    }
+}
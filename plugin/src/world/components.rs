@@ -0,0 +1,174 @@
+use bevy::{
+    prelude::*,
+    reflect::{serde::ReflectSerializer, GetPath, TypeRegistryArc},
+};
+use rweb::{reject::custom, *};
+use serde::Deserialize;
+
+use crate::{
+    reflect::{can_apply, ReflectDefault},
+    serialization::NumberToStringSerializer,
+    sync::{execute_in_world, ExecutionChannel},
+};
+
+use super::{apply_value, deserialize_value, resolve_entity, WorldErrors};
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ComponentPatch {
+    entity: String,
+    component: String,
+    #[serde(default)]
+    path: String,
+    value: serde_json::Value,
+}
+
+free_form_schema!(ComponentPatch);
+
+/// Writes `value` into the field at `path` of an entities component.
+/// An empty path replaces the whole component.
+#[patch("/v1/world/component")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn patch_component(
+    #[json] patch: ComponentPatch,
+) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        apply_component_patch(world, patch)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn apply_component_patch(world: &mut World, patch: ComponentPatch) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let entity = resolve_entity(world, &patch.entity)?;
+    let reflect_component = type_registry
+        .get_with_name(&patch.component)
+        .ok_or(WorldErrors::UnknownType)?
+        .data::<ReflectComponent>()
+        .ok_or(WorldErrors::NotAComponent)?;
+
+    let mut component = reflect_component
+        .reflect_component_mut(world, entity)
+        .ok_or(WorldErrors::ComponentNotFound)?;
+    let field = component
+        .path_mut(&patch.path)
+        .map_err(|_| WorldErrors::InvalidPath)?;
+    let value = deserialize_value(&type_registry, field.type_name(), patch.value)?;
+    apply_value(field, value)?;
+
+    let serializer = ReflectSerializer::new(&*component, &type_registry);
+    Ok(serde_json::to_string(&NumberToStringSerializer(serializer)).unwrap())
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct InsertComponent {
+    entity: String,
+    component: String,
+    value: Option<serde_json::Value>,
+}
+
+free_form_schema!(InsertComponent);
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct RemoveComponent {
+    entity: String,
+    component: String,
+}
+
+/// Inserts a component by its registered type name. Without a `value` the component is
+/// constructed through `ReflectDefault`.
+#[post("/v1/world/component")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn insert_component(
+    #[json] insert: InsertComponent,
+) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        insert_component_in_world(world, insert)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn insert_component_in_world(
+    world: &mut World,
+    insert: InsertComponent,
+) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let entity = resolve_entity(world, &insert.entity)?;
+    let registration = type_registry
+        .get_with_name(&insert.component)
+        .ok_or(WorldErrors::UnknownType)?;
+    let reflect_component = registration
+        .data::<ReflectComponent>()
+        .ok_or(WorldErrors::NotAComponent)?;
+
+    let value = match insert.value {
+        Some(value) => deserialize_value(&type_registry, registration.name(), value)?,
+        None => registration
+            .data::<ReflectDefault>()
+            .ok_or(WorldErrors::NoDefault)?
+            .default(),
+    };
+    if value.type_name() != registration.name() {
+        return Err(WorldErrors::InvalidValue);
+    }
+    // `add_component` applies the value onto a new component, which panics when the value
+    // doesn't fit. Dynamic values are checked against the default the component starts from.
+    if value.any().type_id() != registration.type_id() {
+        let template = registration
+            .data::<ReflectDefault>()
+            .ok_or(WorldErrors::InvalidValue)?
+            .default();
+        if !can_apply(&*template, &*value) {
+            return Err(WorldErrors::InvalidValue);
+        }
+    }
+    reflect_component.add_component(world, entity, &*value);
+
+    let component = reflect_component
+        .reflect_component(world, entity)
+        .ok_or(WorldErrors::ComponentNotFound)?;
+    let serializer = ReflectSerializer::new(component, &type_registry);
+    Ok(serde_json::to_string(&NumberToStringSerializer(serializer)).unwrap())
+}
+
+/// Removes a component from an entity and responds with its last value.
+#[delete("/v1/world/component")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn remove_component(
+    #[query] remove: RemoveComponent,
+) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        remove_component_in_world(world, remove)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn remove_component_in_world(
+    world: &mut World,
+    remove: RemoveComponent,
+) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let entity = resolve_entity(world, &remove.entity)?;
+    let reflect_component = type_registry
+        .get_with_name(&remove.component)
+        .ok_or(WorldErrors::UnknownType)?
+        .data::<ReflectComponent>()
+        .ok_or(WorldErrors::NotAComponent)?;
+
+    let component = reflect_component
+        .reflect_component(world, entity)
+        .ok_or(WorldErrors::ComponentNotFound)?;
+    let serializer = ReflectSerializer::new(component, &type_registry);
+    let json = serde_json::to_string(&NumberToStringSerializer(serializer)).unwrap();
+
+    reflect_component.remove_component(world, entity);
+    Ok(json)
+}
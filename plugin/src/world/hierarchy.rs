@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+use rweb::{reject::custom, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serialization::entity_to_string,
+    sync::{execute_in_world, ExecutionChannel},
+    DevInfo,
+};
+
+use super::{entity_label, resolve_entity, WorldErrors};
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct HierarchyQuery {
+    /// Entity whose children are returned, the root entities if not set.
+    parent: Option<String>,
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct HierarchyNode {
+    entity: String,
+    label: String,
+    /// Name of the entities `DevInfo`.
+    dev_name: Option<String>,
+    /// Value of the entities `Name` component.
+    name: Option<String>,
+    children: usize,
+}
+
+/// One level of the entity hierarchy. Clients expand nodes lazily by requesting the children
+/// of a node with `parent`.
+#[get("/v1/world/hierarchy")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn hierarchy(
+    #[query] query: HierarchyQuery,
+) -> Result<Json<Vec<HierarchyNode>>, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        hierarchy_in_world(world, query)
+    })
+    .await
+    .map(Json::from)
+    .map_err(custom)
+}
+
+fn hierarchy_in_world(
+    world: &mut World,
+    query: HierarchyQuery,
+) -> Result<Vec<HierarchyNode>, WorldErrors> {
+    let entities = match &query.parent {
+        Some(parent) => {
+            let parent = resolve_entity(world, parent)?;
+            match world.get::<Children>(parent) {
+                Some(children) => children.to_vec(),
+                None => Vec::new(),
+            }
+        }
+        None => {
+            let mut roots = world
+                .query_filtered::<Entity, Without<Parent>>()
+                .iter(world)
+                .collect::<Vec<_>>();
+            roots.sort_by_key(|entity| entity.id());
+            roots
+        }
+    };
+
+    Ok(entities
+        .into_iter()
+        .filter(|entity| world.get_entity(*entity).is_some())
+        .map(|entity| HierarchyNode {
+            entity: entity_to_string(entity),
+            label: entity_label(world, entity),
+            dev_name: world.get::<DevInfo>(entity).map(|info| info.name.clone()),
+            name: world.get::<Name>(entity).map(|name| name.to_string()),
+            children: world
+                .get::<Children>(entity)
+                .map(|children| children.len())
+                .unwrap_or(0),
+        })
+        .collect())
+}
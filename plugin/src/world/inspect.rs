@@ -0,0 +1,138 @@
+use bevy::{
+    ecs::component::{ComponentId, ComponentTicks, StorageType},
+    prelude::*,
+    reflect::{serde::ReflectSerializer, TypeRegistryArc},
+};
+use rweb::{reject::custom, *};
+use serde::Serialize;
+
+use crate::{
+    serialization::{entity_to_string, NumberToStringSerializer},
+    sync::{execute_in_world, ExecutionChannel},
+    DevInfo,
+};
+
+use super::{entity_label, resolve_entity, WorldErrors};
+
+#[derive(Serialize)]
+struct EntityDetails<'a> {
+    entity: String,
+    label: String,
+    archetype: usize,
+    table: usize,
+    change_tick: u32,
+    last_change_tick: u32,
+    dev_info: Option<&'a DevInfo>,
+    components: Vec<ComponentDetails<'a>>,
+}
+
+#[derive(Serialize)]
+struct ComponentDetails<'a> {
+    #[serde(rename = "type")]
+    type_name: &'a str,
+    storage: &'static str,
+    added: Option<u32>,
+    changed: Option<u32>,
+    /// `None` for components that aren't registered with `ReflectComponent`.
+    value: Option<ReflectSerializer<'a>>,
+}
+
+#[get("/v1/world/entity/{id}")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn inspect_entity(id: String) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        inspect_entity_in_world(world, &id)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn inspect_entity_in_world(world: &mut World, id: &str) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let entity = resolve_entity(world, id)?;
+    let entity_ref = world.entity(entity);
+    let archetype = entity_ref.archetype();
+    let change_tick = world.read_change_tick();
+
+    let components = archetype
+        .components()
+        .filter_map(|component_id| {
+            let info = world.components().get_info(component_id)?;
+            let storage = match info.storage_type() {
+                StorageType::Table => "Table",
+                StorageType::SparseSet => "SparseSet",
+            };
+            let ticks = component_ticks(world, entity, component_id);
+            let value = info
+                .type_id()
+                .and_then(|type_id| type_registry.get(type_id))
+                .and_then(|registration| registration.data::<ReflectComponent>())
+                .and_then(|reflect_component| reflect_component.reflect_component(world, entity))
+                .map(|component| ReflectSerializer::new(component, &type_registry));
+
+            Some(ComponentDetails {
+                type_name: info.name(),
+                storage,
+                added: ticks.map(|ticks| {
+                    recover_tick(|last| ticks.is_added(last, change_tick), change_tick)
+                }),
+                changed: ticks.map(|ticks| {
+                    recover_tick(|last| ticks.is_changed(last, change_tick), change_tick)
+                }),
+                value,
+            })
+        })
+        .collect();
+
+    let details = EntityDetails {
+        entity: entity_to_string(entity),
+        label: entity_label(world, entity),
+        archetype: archetype.id().index(),
+        table: archetype.table_id().index(),
+        change_tick,
+        last_change_tick: world.last_change_tick(),
+        dev_info: world.get::<DevInfo>(entity),
+        components,
+    };
+    Ok(serde_json::to_string(&NumberToStringSerializer(details)).unwrap())
+}
+
+pub(crate) fn component_ticks(
+    world: &World,
+    entity: Entity,
+    component_id: ComponentId,
+) -> Option<&ComponentTicks> {
+    let location = world.entities().get(entity)?;
+    let archetype = world.archetypes().get(location.archetype_id)?;
+    match archetype.get_storage_type(component_id)? {
+        StorageType::Table => {
+            let table = world.storages().tables.get(archetype.table_id())?;
+            let column = table.get_column(component_id)?;
+            let row = archetype.entity_table_row(location.index);
+            // SAFE: the row comes from the entities current location and is in bounds.
+            Some(unsafe { column.get_ticks_unchecked(row) })
+        }
+        StorageType::SparseSet => world
+            .storages()
+            .sparse_sets
+            .get(component_id)?
+            .get_ticks(entity),
+    }
+}
+
+/// `ComponentTicks` keeps its raw ticks private and only allows comparisons, so the tick
+/// is recovered by searching for the oldest `last_change_tick` it is still newer than.
+fn recover_tick(is_newer_than: impl Fn(u32) -> bool, change_tick: u32) -> u32 {
+    let (mut low, mut high) = (1, u32::MAX);
+    while low < high {
+        let age = low + (high - low) / 2;
+        if is_newer_than(change_tick.wrapping_sub(age)) {
+            high = age;
+        } else {
+            low = age + 1;
+        }
+    }
+    change_tick.wrapping_sub(low - 1)
+}
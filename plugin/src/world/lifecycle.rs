@@ -0,0 +1,270 @@
+use bevy::{prelude::*, reflect::TypeRegistryArc};
+use rweb::{reject::custom, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serialization::entity_to_string,
+    sync::{execute_in_world, ExecutionChannel},
+    DevInfo,
+};
+
+use super::{resolve_entity, WorldErrors};
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct SpawnedEntity {
+    entity: String,
+}
+
+#[derive(Serialize, Schema, Debug)]
+pub(crate) struct DespawnedEntities {
+    entities: Vec<String>,
+}
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct SpawnEntity {
+    name: String,
+}
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct DespawnOptions {
+    recursive: Option<bool>,
+}
+
+#[derive(Deserialize, Schema, Debug)]
+pub(crate) struct SetParent {
+    parent: Option<String>,
+}
+
+#[post("/v1/world/entity")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn spawn_entity(
+    #[json] spawn: SpawnEntity,
+) -> Result<Json<SpawnedEntity>, rweb::Rejection> {
+    let entity = execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        world
+            .spawn()
+            .insert(DevInfo {
+                name: spawn.name,
+                module: module_path!().to_string(),
+                ..Default::default()
+            })
+            .id()
+    })
+    .await;
+
+    Ok(SpawnedEntity {
+        entity: entity_to_string(entity),
+    }
+    .into())
+}
+
+#[delete("/v1/world/entity/{id}")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn despawn_entity(
+    id: String,
+    #[query] options: DespawnOptions,
+) -> Result<Json<DespawnedEntities>, rweb::Rejection> {
+    let recursive = options.recursive.unwrap_or(false);
+    let despawned = execute_in_world(
+        ExecutionChannel::FrameEnd,
+        move |world| -> Result<_, WorldErrors> {
+            let entity = resolve_entity(world, &id)?;
+            Ok(despawn(world, entity, recursive))
+        },
+    )
+    .await
+    .map_err(custom)?;
+
+    Ok(DespawnedEntities {
+        entities: despawned.into_iter().map(entity_to_string).collect(),
+    }
+    .into())
+}
+
+#[post("/v1/world/entity/{id}/clone")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn clone_entity(id: String) -> Result<Json<SpawnedEntity>, rweb::Rejection> {
+    let entity = execute_in_world(
+        ExecutionChannel::FrameEnd,
+        move |world| -> Result<_, WorldErrors> {
+            let entity = resolve_entity(world, &id)?;
+            Ok(clone(world, entity))
+        },
+    )
+    .await
+    .map_err(custom)?;
+
+    Ok(SpawnedEntity {
+        entity: entity_to_string(entity),
+    }
+    .into())
+}
+
+#[post("/v1/world/entity/{id}/parent")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn set_parent(
+    id: String,
+    #[json] set_parent: SetParent,
+) -> Result<Json<SpawnedEntity>, rweb::Rejection> {
+    let entity = execute_in_world(
+        ExecutionChannel::FrameEnd,
+        move |world| -> Result<_, WorldErrors> {
+            let entity = resolve_entity(world, &id)?;
+            let parent = match set_parent.parent {
+                Some(parent) => Some(resolve_entity(world, &parent)?),
+                None => None,
+            };
+            reparent(world, entity, parent)?;
+            Ok(entity)
+        },
+    )
+    .await
+    .map_err(custom)?;
+
+    Ok(SpawnedEntity {
+        entity: entity_to_string(entity),
+    }
+    .into())
+}
+
+/// Despawns `entity` and returns every entity that was removed. Without `recursive` the
+/// children of `entity` are detached and kept alive.
+fn despawn(world: &mut World, entity: Entity, recursive: bool) -> Vec<Entity> {
+    if recursive {
+        let mut despawned = vec![entity];
+        collect_descendants(world, entity, &mut despawned);
+        despawn_with_children_recursive(world, entity);
+        return despawned;
+    }
+
+    // `remove_children` panics on entities that are gone, which hierarchies can still point at
+    // when they were despawned without updating them.
+    let children = world
+        .get::<Children>(entity)
+        .map(|children| {
+            children
+                .iter()
+                .copied()
+                .filter(|child| world.get_entity(*child).is_some())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    world.entity_mut(entity).remove_children(&children);
+    if let Some(parent) = world.get::<Parent>(entity).map(|parent| parent.0) {
+        if world.get_entity(parent).is_some() {
+            world.entity_mut(parent).remove_children(&[entity]);
+        }
+    }
+    world.despawn(entity);
+    vec![entity]
+}
+
+fn collect_descendants(world: &World, entity: Entity, output: &mut Vec<Entity>) {
+    if let Some(children) = world.get::<Children>(entity) {
+        for child in children.iter() {
+            if world.get_entity(*child).is_none() {
+                continue;
+            }
+            output.push(*child);
+            collect_descendants(world, *child, output);
+        }
+    }
+}
+
+/// Spawns a copy of `entity` with all of its reflectable components. The copy is
+/// attached to the same parent, children are not copied.
+fn clone(world: &mut World, entity: Entity) -> Entity {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let components = world
+        .entity(entity)
+        .archetype()
+        .components()
+        .filter_map(|component_id| world.components().get_info(component_id)?.type_id())
+        .filter(|type_id| {
+            ![
+                std::any::TypeId::of::<Parent>(),
+                std::any::TypeId::of::<PreviousParent>(),
+                std::any::TypeId::of::<Children>(),
+            ]
+            .contains(type_id)
+        })
+        .filter_map(|type_id| type_registry.get(type_id)?.data::<ReflectComponent>())
+        .filter_map(|reflect_component| {
+            let value = reflect_component.reflect_component(world, entity)?;
+            Some((reflect_component, value.clone_value()))
+        })
+        .collect::<Vec<_>>();
+
+    let clone = world.spawn().id();
+    for (reflect_component, value) in components {
+        reflect_component.add_component(world, clone, &*value);
+    }
+
+    if let Some(parent) = world.get::<Parent>(entity).map(|parent| parent.0) {
+        world.entity_mut(parent).push_children(&[clone]);
+    }
+
+    clone
+}
+
+/// Moves `entity` below `parent` or turns it into a root entity if `parent` is `None`.
+fn reparent(world: &mut World, entity: Entity, parent: Option<Entity>) -> Result<(), WorldErrors> {
+    // Refuse to attach an entity to itself or one of its own descendants.
+    let mut ancestor = parent;
+    while let Some(current) = ancestor {
+        if current == entity {
+            return Err(WorldErrors::InvalidHierarchy);
+        }
+        ancestor = world.get::<Parent>(current).map(|parent| parent.0);
+    }
+
+    if let Some(previous_parent) = world.get::<Parent>(entity).map(|parent| parent.0) {
+        world.entity_mut(previous_parent).remove_children(&[entity]);
+    }
+    if let Some(parent) = parent {
+        world.entity_mut(parent).push_children(&[entity]);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_of_despawned_entities_do_not_resolve_to_reused_indices() {
+        let mut world = World::new();
+        let entity = world.spawn().id();
+        let id = entity_to_string(entity);
+        world.despawn(entity);
+        let reused = world.spawn().id();
+        assert_eq!(reused.id(), entity.id());
+        assert!(resolve_entity(&world, &id).is_err());
+        assert_eq!(
+            resolve_entity(&world, &entity_to_string(reused)).unwrap(),
+            reused
+        );
+        assert_eq!(
+            resolve_entity(&world, &reused.id().to_string()).unwrap(),
+            reused
+        );
+    }
+
+    #[test]
+    fn despawn_skips_despawned_relatives() {
+        let mut world = World::new();
+        let parent = world.spawn().id();
+        let entity = world.spawn().id();
+        let child = world.spawn().id();
+        world.entity_mut(parent).push_children(&[entity]);
+        world.entity_mut(entity).push_children(&[child]);
+        world.despawn(parent);
+        world.despawn(child);
+
+        assert_eq!(despawn(&mut world, entity, false), vec![entity]);
+        assert!(world.get_entity(entity).is_none());
+    }
+}
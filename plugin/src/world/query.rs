@@ -0,0 +1,207 @@
+use std::any::TypeId;
+
+use bevy::{
+    ecs::component::ComponentId,
+    prelude::*,
+    reflect::{serde::ReflectSerializer, TypeRegistryArc, TypeRegistryInternal},
+};
+use rweb::{reject::custom, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serialization::{entity_to_string, NumberToStringSerializer},
+    sync::{execute_in_world, ExecutionChannel},
+    DevInfo,
+};
+
+use super::{entity_label, resolve_entity, WorldErrors};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize, Schema, Debug, Default)]
+#[serde(default)]
+pub(crate) struct WorldQuery {
+    /// Type names of components an entity must have.
+    with: Vec<String>,
+    /// Type names of components an entity must not have.
+    without: Vec<String>,
+    /// Substring of the entities `Name` or `DevInfo` name.
+    name: Option<String>,
+    /// Only entities that are direct children of this entity.
+    parent: Option<String>,
+    /// Type names of components that are included in the output, all reflectable
+    /// components if not set.
+    components: Option<Vec<String>>,
+    /// Entity id after which the page starts, taken from `next_cursor` of the previous page.
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct QueryPage<'a> {
+    entities: Vec<QueryEntity<'a>>,
+    total: usize,
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QueryEntity<'a> {
+    entity: String,
+    label: String,
+    components: Vec<ReflectSerializer<'a>>,
+}
+
+/// Filtered and paginated alternative to `/v1/world` that only serializes what is asked for.
+/// Entities are ordered by their id so cursors stay valid while the world changes.
+#[post("/v1/world/query")]
+#[cors(origins("*"), headers("content-type"))]
+pub(crate) async fn query_world(#[json] query: WorldQuery) -> Result<String, rweb::Rejection> {
+    execute_in_world(ExecutionChannel::FrameEnd, move |world| {
+        query_world_in_world(world, query)
+    })
+    .await
+    .map_err(custom)
+}
+
+fn query_world_in_world(world: &mut World, query: WorldQuery) -> Result<String, WorldErrors> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    let with = resolve_component_ids(world, &type_registry, &query.with)?;
+    let without = resolve_component_ids(world, &type_registry, &query.without)?;
+    let parent = match &query.parent {
+        Some(parent) => Some(resolve_entity(world, parent)?),
+        None => None,
+    };
+    // The entity of a cursor may be gone by now, only its position in the order matters.
+    let cursor = match &query.cursor {
+        Some(cursor) => Some(
+            cursor
+                .parse::<u32>()
+                .map_err(|_| WorldErrors::EntityNotFound)?,
+        ),
+        None => None,
+    };
+    let included = match &query.components {
+        Some(components) => Some(
+            components
+                .iter()
+                .map(|name| {
+                    type_registry
+                        .get_with_name(name)
+                        .map(|registration| registration.type_id())
+                        .ok_or(WorldErrors::UnknownType)
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    // A component that was never added to the world can't be on any entity.
+    if with.iter().any(Option::is_none) {
+        return Ok(serialize_page(QueryPage {
+            entities: Vec::new(),
+            total: 0,
+            next_cursor: None,
+        }));
+    }
+
+    let mut entities = world
+        .archetypes()
+        .iter()
+        .filter(|archetype| {
+            with.iter().flatten().all(|id| archetype.contains(*id))
+                && !without.iter().flatten().any(|id| archetype.contains(*id))
+        })
+        .flat_map(|archetype| archetype.entities().iter().copied())
+        .filter(|entity| {
+            if let Some(name) = &query.name {
+                let info = world.get::<DevInfo>(*entity);
+                let bevy_name = world.get::<Name>(*entity);
+                if !matches!(info, Some(info) if info.name.contains(name.as_str()))
+                    && !matches!(bevy_name, Some(bevy_name) if bevy_name.as_str().contains(name.as_str()))
+                {
+                    return false;
+                }
+            }
+            if let Some(parent) = parent {
+                if world.get::<Parent>(*entity).map(|p| p.0) != Some(parent) {
+                    return false;
+                }
+            }
+            true
+        })
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|entity| entity.id());
+
+    let total = entities.len();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let start = cursor.map_or(0, |cursor| {
+        entities.partition_point(|entity| entity.id() <= cursor)
+    });
+    let end = (start + limit).min(total);
+    let next_cursor = if end < total && end > start {
+        Some(entity_to_string(entities[end - 1]))
+    } else {
+        None
+    };
+
+    let page = entities[start..end]
+        .iter()
+        .map(|entity| QueryEntity {
+            entity: entity_to_string(*entity),
+            label: entity_label(world, *entity),
+            components: reflect_components(world, &type_registry, *entity, included.as_deref()),
+        })
+        .collect();
+
+    Ok(serialize_page(QueryPage {
+        entities: page,
+        total,
+        next_cursor,
+    }))
+}
+
+fn serialize_page(page: QueryPage) -> String {
+    serde_json::to_string(&NumberToStringSerializer(page)).unwrap()
+}
+
+/// Maps type names to the component ids of the world. `None` means the type is registered
+/// but was never used as component.
+fn resolve_component_ids(
+    world: &World,
+    type_registry: &TypeRegistryInternal,
+    names: &[String],
+) -> Result<Vec<Option<ComponentId>>, WorldErrors> {
+    names
+        .iter()
+        .map(|name| {
+            let registration = type_registry
+                .get_with_name(name)
+                .ok_or(WorldErrors::UnknownType)?;
+            Ok(world.components().get_id(registration.type_id()))
+        })
+        .collect()
+}
+
+/// Collects all reflectable components of `entity`, limited to `included` if set.
+pub(crate) fn reflect_components<'a>(
+    world: &'a World,
+    type_registry: &'a TypeRegistryInternal,
+    entity: Entity,
+    included: Option<&[TypeId]>,
+) -> Vec<ReflectSerializer<'a>> {
+    world
+        .entity(entity)
+        .archetype()
+        .components()
+        .filter_map(|component_id| world.components().get_info(component_id)?.type_id())
+        .filter(|type_id| match included {
+            Some(included) => included.contains(type_id),
+            None => true,
+        })
+        .filter_map(|type_id| type_registry.get(type_id)?.data::<ReflectComponent>())
+        .filter_map(|reflect_component| reflect_component.reflect_component(world, entity))
+        .map(|component| ReflectSerializer::new(component, type_registry))
+        .collect()
+}
@@ -0,0 +1,293 @@
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use bevy::{
+    ecs::archetype::ArchetypeId,
+    prelude::*,
+    reflect::{serde::ReflectSerializer, TypeRegistryArc},
+};
+use futures::{future, SinkExt, StreamExt};
+use rweb::{
+    filters::BoxedFilter,
+    warp::ws::{Message, WebSocket, Ws},
+    *,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{channel, error::TrySendError, Sender};
+
+use crate::serialization::{entity_from_string, entity_to_string, NumberToStringSerializer};
+
+use super::inspect::component_ticks;
+
+/// Message a client sends over the socket to choose what it wants to watch. Spawned and
+/// despawned entities are always streamed, component changes only for watched entities.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Subscription {
+    entities: Vec<String>,
+    all_entities: bool,
+    /// Type names of watched components, all reflectable components if not set.
+    components: Option<Vec<String>>,
+}
+
+struct Subscriber {
+    id: u64,
+    subscription: Subscription,
+    sender: Sender<String>,
+    /// Whether changes were dropped because the client didn't keep up, it is sent a
+    /// `RESYNC` message instead of further changes until there is room for it.
+    lagging: bool,
+}
+
+/// Messages buffered per subscriber before it counts as lagging.
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// Tells a lagging client that changes were dropped and it needs to fetch the world again.
+const RESYNC: &str = r#"{"resync":true}"#;
+
+#[derive(Serialize)]
+struct FrameChanges<'a> {
+    spawned: &'a [String],
+    despawned: &'a [String],
+    changed: Vec<ChangedComponent<'a>>,
+    removed: Vec<RemovedComponent<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChangedComponent<'a> {
+    entity: String,
+    component: ReflectSerializer<'a>,
+}
+
+#[derive(Serialize)]
+struct RemovedComponent<'a> {
+    entity: String,
+    component: &'a str,
+}
+
+lazy_static::lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+}
+
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Bookkeeping of the previous frame, only maintained while someone is subscribed.
+#[derive(Default)]
+pub(crate) struct WorldStreamState {
+    last_change_tick: u32,
+    archetypes: Option<HashMap<Entity, ArchetypeId>>,
+}
+
+/// WebSocket at `/v1/world/stream` that sends one `FrameChanges` message per frame with
+/// entity and component changes since the previous one. Clients that fall behind get a
+/// `{"resync":true}` message in place of the changes they missed.
+pub(crate) fn world_stream() -> BoxedFilter<(impl Reply,)> {
+    warp::path!("v1" / "world" / "stream")
+        .and(warp::ws())
+        .map(|ws: Ws| ws.on_upgrade(handle_socket))
+        .boxed()
+}
+
+async fn handle_socket(socket: WebSocket) {
+    let (mut socket_tx, mut socket_rx) = socket.split();
+    let (sender, mut receiver) = channel(SUBSCRIBER_BUFFER);
+    let id = NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed);
+    SUBSCRIBERS.lock().unwrap().push(Subscriber {
+        id,
+        subscription: Subscription::default(),
+        sender,
+        lagging: false,
+    });
+
+    let forward = async move {
+        while let Some(changes) = receiver.recv().await {
+            if socket_tx.send(Message::text(changes)).await.is_err() {
+                break;
+            }
+        }
+    };
+    let subscribe = async move {
+        while let Some(Ok(message)) = socket_rx.next().await {
+            let subscription = message
+                .to_str()
+                .ok()
+                .and_then(|text| serde_json::from_str::<Subscription>(text).ok());
+            if let Some(subscription) = subscription {
+                let mut subscribers = SUBSCRIBERS.lock().unwrap();
+                if let Some(subscriber) = subscribers.iter_mut().find(|s| s.id == id) {
+                    subscriber.subscription = subscription;
+                }
+            }
+        }
+    };
+    future::select(Box::pin(forward), Box::pin(subscribe)).await;
+
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.id != id);
+}
+
+pub(crate) fn stream_world_changes(world: &mut World) {
+    world.resource_scope(|world, mut state: Mut<WorldStreamState>| {
+        stream_changes(world, &mut state);
+    });
+}
+
+fn stream_changes(world: &World, state: &mut WorldStreamState) {
+    let change_tick = world.read_change_tick();
+    let last_change_tick = state.last_change_tick;
+    state.last_change_tick = change_tick;
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        state.archetypes = None;
+        return;
+    }
+
+    let archetypes = world
+        .archetypes()
+        .iter()
+        .flat_map(|archetype| {
+            archetype
+                .entities()
+                .iter()
+                .map(move |entity| (*entity, archetype.id()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    // The first frame of a stream only records the world, there is nothing to compare to.
+    let previous = match state.archetypes.replace(archetypes) {
+        Some(previous) => previous,
+        None => return,
+    };
+    let current = state.archetypes.as_ref().unwrap();
+
+    let spawned = current
+        .keys()
+        .filter(|entity| !previous.contains_key(entity))
+        .map(|entity| entity_to_string(*entity))
+        .collect::<Vec<_>>();
+    let despawned = previous
+        .keys()
+        .filter(|entity| !current.contains_key(entity))
+        .map(|entity| entity_to_string(*entity))
+        .collect::<Vec<_>>();
+
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    let type_registry = type_registry.read();
+
+    subscribers.retain_mut(|subscriber| {
+        if subscriber.lagging {
+            return match subscriber.sender.try_send(RESYNC.to_string()) {
+                Ok(()) => {
+                    subscriber.lagging = false;
+                    true
+                }
+                Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Closed(_)) => false,
+            };
+        }
+        let subscription = &subscriber.subscription;
+        let watched = if subscription.all_entities {
+            current.keys().copied().collect::<Vec<_>>()
+        } else {
+            subscription
+                .entities
+                .iter()
+                .filter_map(|id| entity_from_string(world, id))
+                .filter(|entity| current.contains_key(entity))
+                .collect()
+        };
+        let components = subscription.components.as_ref().map(|names| {
+            names
+                .iter()
+                .filter_map(|name| type_registry.get_with_name(name))
+                .map(|registration| registration.type_id())
+                .collect::<HashSet<TypeId>>()
+        });
+        let is_watched = |type_id: &TypeId| match &components {
+            Some(components) => components.contains(type_id),
+            None => true,
+        };
+
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for entity in watched {
+            let archetype = world.archetypes().get(current[&entity]).unwrap();
+            for component_id in archetype.components() {
+                let info = world.components().get_info(component_id).unwrap();
+                let type_id = match info.type_id() {
+                    Some(type_id) if is_watched(&type_id) => type_id,
+                    _ => continue,
+                };
+                let is_changed = component_ticks(world, entity, component_id)
+                    .map(|ticks| ticks.is_changed(last_change_tick, change_tick))
+                    .unwrap_or(false);
+                if !is_changed {
+                    continue;
+                }
+                let component = type_registry
+                    .get(type_id)
+                    .and_then(|registration| registration.data::<ReflectComponent>())
+                    .and_then(|reflect_component| {
+                        reflect_component.reflect_component(world, entity)
+                    });
+                if let Some(component) = component {
+                    changed.push(ChangedComponent {
+                        entity: entity_to_string(entity),
+                        component: ReflectSerializer::new(component, &type_registry),
+                    });
+                }
+            }
+
+            // Components that disappeared show up as an archetype change.
+            let previous_archetype = previous
+                .get(&entity)
+                .and_then(|id| world.archetypes().get(*id));
+            if let Some(previous_archetype) = previous_archetype {
+                if previous_archetype.id() == archetype.id() {
+                    continue;
+                }
+                for component_id in previous_archetype.components() {
+                    if archetype.contains(component_id) {
+                        continue;
+                    }
+                    let info = world.components().get_info(component_id).unwrap();
+                    if matches!(info.type_id(), Some(type_id) if is_watched(&type_id)) {
+                        removed.push(RemovedComponent {
+                            entity: entity_to_string(entity),
+                            component: info.name(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if spawned.is_empty() && despawned.is_empty() && changed.is_empty() && removed.is_empty() {
+            return true;
+        }
+        let changes = FrameChanges {
+            spawned: &spawned,
+            despawned: &despawned,
+            changed,
+            removed,
+        };
+        let json = serde_json::to_string(&NumberToStringSerializer(changes)).unwrap();
+        // Drop subscribers whose socket is already gone.
+        match subscriber.sender.try_send(json) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                subscriber.lagging = true;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    });
+}